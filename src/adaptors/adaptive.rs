@@ -1,63 +1,138 @@
 use rayon::iter::plumbing::*;
-// use std::fmt::Debug;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use rayon::iter::*;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
 
-use crossbeam::channel::Receiver;
-use crossbeam::channel::Sender;
-use rayon::current_num_threads;
-
-struct AdaptiveProducer<'f, P> {
-    producer: Option<P>,
-    sender: Sender<Option<(usize, P)>>,
-    receiver: Receiver<Option<(usize, P)>>,
-    stealers: &'f AtomicUsize,
-    workers: &'f AtomicUsize,
-    size: usize,
-    block_size: usize,
+/// How many items an adaptive task processes before checking for steal requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockPolicy {
+    /// Always process blocks of the given size.
+    Fixed(usize),
+    /// Start with blocks of the given size and double them while nobody steals.
+    /// Sizes go back to the initial size after each steal.
+    /// This keeps the polling overhead logarithmic for long sequential runs.
+    Geometric(usize),
 }
 
-impl<'f, P: Producer> AdaptiveProducer<'f, P> {
-    fn stolen(&self) -> bool {
-        if self.producer.is_some() && self.stealers.load(Ordering::SeqCst) != 0 {
-            let mut stealer_count = self.stealers.load(Ordering::SeqCst);
-            while stealer_count != 0 {
-                match self.stealers.compare_exchange(
-                    stealer_count,
-                    stealer_count - 1,
-                    Ordering::SeqCst,
-                    Ordering::SeqCst,
-                ) {
-                    Ok(_) => {
-                        return true;
-                    }
-                    Err(new_stealer_count) => stealer_count = new_stealer_count,
-                }
-            }
-            false
-        } else {
-            false
+impl BlockPolicy {
+    pub(crate) fn initial_size(&self) -> usize {
+        match *self {
+            BlockPolicy::Fixed(size) | BlockPolicy::Geometric(size) => size,
         }
     }
-    fn give_work(&mut self) {
-        let mid = self.size / 2;
-        let right_size = self.size - mid;
-        self.size = mid;
-        let (left_producer, right_producer) = self.producer.take().unwrap().split_at(mid);
-        self.workers.fetch_add(1, Ordering::SeqCst);
-        self.sender
-            .send(Some((right_size, right_producer)))
-            .expect("failed sending work");
-        self.producer = Some(left_producer);
-    }
-    fn stop_working(&mut self) {
-        // everything done. terminate waiting stealers and return the folder
-        let workers = self.workers.fetch_sub(1, Ordering::SeqCst) - 1;
-        if workers == 0 {
-            for _ in 0..current_num_threads() {
-                self.sender.send(None).expect("Failed to send on channel");
-            }
+
+    /// Size of the block following a block of given size.
+    pub(crate) fn next_size(&self, size: usize) -> usize {
+        match *self {
+            BlockPolicy::Fixed(_) => size,
+            BlockPolicy::Geometric(_) => size.saturating_mul(2),
         }
     }
+}
+
+/// Fixed blocks of given size.
+impl From<usize> for BlockPolicy {
+    fn from(block_size: usize) -> Self {
+        BlockPolicy::Fixed(block_size)
+    }
+}
+
+/// `Adaptive` is a parallel iterator which only divides its work when
+/// idle threads ask for some.
+///
+/// This struct is created by the [`adaptive()`] method on [`IndexedParallelIterator`]
+/// [`adaptive()`]: trait.IndexedParallelIterator.html#method.adaptive
+/// [`IndexedParallelIterator`]: trait.IndexedParallelIterator.html
+#[must_use = "iterator adaptors are lazy and do nothing unless consumed"]
+#[derive(Debug, Clone)]
+pub struct Adaptive<I> {
+    base: I,
+    policy: BlockPolicy,
+}
+
+impl<I> Adaptive<I> {
+    pub(crate) fn new(base: I, policy: BlockPolicy) -> Self {
+        assert!(policy.initial_size() > 0, "blocks cannot be empty");
+        Adaptive { base, policy }
+    }
+}
+
+impl<I> ParallelIterator for Adaptive<I>
+where
+    I: IndexedParallelIterator,
+{
+    type Item = I::Item;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        let len = self.base.len();
+        self.base.with_producer(AdaptiveCallback {
+            consumer,
+            policy: self.policy,
+            len,
+        })
+    }
+}
+
+struct AdaptiveCallback<C> {
+    consumer: C,
+    policy: BlockPolicy,
+    len: usize,
+}
+
+impl<T, C> ProducerCallback<T> for AdaptiveCallback<C>
+where
+    C: UnindexedConsumer<T>,
+{
+    type Output = C::Result;
+
+    fn callback<P: Producer<Item = T>>(self, producer: P) -> Self::Output {
+        let adaptive_producer = AdaptiveProducer {
+            producer: Some(producer),
+            size: self.len,
+            block_size: self.policy.initial_size(),
+            policy: self.policy,
+        };
+        adaptive_consume(adaptive_producer, self.consumer)
+    }
+}
+
+/// States of a worker's offer.
+/// Nobody asked for work yet.
+const OPEN: u8 = 0;
+/// An idle thread asked for work.
+const REQUESTED: u8 = 1;
+/// The offer was executed by the worker's thread itself, it needs to be renewed.
+const EXPIRED: u8 = 2;
+
+/// Sequential work which can give part of itself away.
+pub(crate) trait AdaptiveWork: Send {
+    /// Type of items produced.
+    type Item;
+    /// Fold the next block of items into `folder`.
+    fn fold_block<F>(&mut self, folder: F) -> F
+    where
+        F: Folder<Self::Item>;
+    /// Is there enough work left to share some ?
+    fn divisible(&self) -> bool;
+    /// Give away the last part of the remaining work.
+    fn give_work(&mut self) -> Self;
+    fn all_is_completed(&self) -> bool;
+}
+
+struct AdaptiveProducer<P> {
+    producer: Option<P>,
+    size: usize,
+    /// Size of the next block.
+    block_size: usize,
+    policy: BlockPolicy,
+}
+
+impl<P: Producer> AdaptiveWork for AdaptiveProducer<P> {
+    type Item = P::Item;
+
     fn fold_block<F>(&mut self, mut folder: F) -> F
     where
         F: Folder<P::Item>,
@@ -66,65 +141,106 @@ impl<'f, P: Producer> AdaptiveProducer<'f, P> {
         if self.block_size >= self.size {
             folder = folder.consume_iter(producer.into_iter());
             self.size = 0;
-            self.stop_working();
         } else {
             let (left, right) = producer.split_at(self.block_size);
             folder = folder.consume_iter(left.into_iter());
             self.producer = Some(right);
             self.size -= self.block_size;
+            self.block_size = self.policy.next_size(self.block_size);
         }
-        return folder;
+        folder
     }
 
-    fn steal(&mut self) {
-        self.stealers.fetch_add(1, Ordering::SeqCst);
+    fn divisible(&self) -> bool {
+        self.size > 1
+    }
 
-        if self.workers.load(Ordering::SeqCst) == 0 {
-            return;
-        }
-        let stolen_task = self.receiver.recv().expect("Failed to receive on channel");
-        if let Some((size, producer)) = stolen_task {
-            self.size = size;
-            self.producer = Some(producer);
+    fn give_work(&mut self) -> Self {
+        let mid = self.size / 2;
+        let right_size = self.size - mid;
+        self.size = mid;
+        let (left_producer, right_producer) = self.producer.take().unwrap().split_at(mid);
+        self.producer = Some(left_producer);
+        self.block_size = self.policy.initial_size();
+        AdaptiveProducer {
+            producer: Some(right_producer),
+            size: right_size,
+            block_size: self.block_size,
+            policy: self.policy,
         }
     }
 
     fn all_is_completed(&self) -> bool {
-        self.workers.load(Ordering::SeqCst) != 0
+        self.producer.is_none()
     }
 }
 
-impl<'f, P: Producer> UnindexedProducer for AdaptiveProducer<'f, P> {
-    type Item = P::Item;
+/// Process all work with given consumer.
+pub(crate) fn adaptive_consume<W, C>(producer: W, consumer: C) -> C::Result
+where
+    W: AdaptiveWork,
+    C: UnindexedConsumer<W::Item>,
+{
+    let folder = consumer.split_off_left().into_folder();
+    work(producer, folder, consumer)
+}
 
-    fn split(self) -> (Self, Option<Self>) {
-        let right = AdaptiveProducer {
-            producer: None,
-            sender: self.sender.clone(),
-            receiver: self.receiver.clone(),
-            stealers: self.stealers,
-            workers: self.workers,
-            size: 0,
-            block_size: self.block_size,
+/// Fold all work into `folder`, giving some to thieves when they ask.
+/// `remaining` is the consumer for all items after the ones of `producer`,
+/// it is used to process stolen work.
+///
+/// All items folded here end up in the same folder: we only reduce
+/// with the result of stolen work.
+///
+/// Thieves never wait for the worker: they register on the worker's offer
+/// and the worker spawns the stolen work at the end of its current block.
+/// Since nobody blocks, this works inside any thread pool and with nested
+/// adaptive iterators.
+fn work<W, C>(mut producer: W, mut folder: C::Folder, remaining: C) -> C::Result
+where
+    W: AdaptiveWork,
+    C: UnindexedConsumer<W::Item>,
+{
+    let reducer = remaining.to_reducer();
+    let offer = AtomicU8::new(OPEN);
+    let thief_result = Mutex::new(None);
+    let worker_result = rayon::in_place_scope(|scope| {
+        let worker_thread = rayon::current_thread_index();
+        let offer = &offer;
+        let thief_result = &thief_result;
+        // this task is only useful if an idle thread steals it
+        let make_offer = move |_: &rayon::Scope| {
+            let state = if rayon::current_thread_index() == worker_thread {
+                EXPIRED
+            } else {
+                REQUESTED
+            };
+            offer.store(state, Ordering::Relaxed);
         };
-        (self, Some(right))
-    }
-
-    fn fold_with<F>(mut self, mut folder: F) -> F
-    where
-        F: Folder<Self::Item>,
-    {
-        while !self.all_is_completed() {
-            if self.producer.is_some() {
-                if self.size > 1 && self.stolen() {
-                    self.give_work();
-                } else {
-                    folder = self.fold_block(folder);
+        scope.spawn(make_offer);
+        while !producer.all_is_completed() && !folder.full() {
+            match offer.load(Ordering::Relaxed) {
+                REQUESTED if producer.divisible() => {
+                    let stolen = producer.give_work();
+                    let kept_remaining = remaining.split_off_left();
+                    scope.spawn(move |_| {
+                        *thief_result.lock().unwrap() = Some(adaptive_consume(stolen, remaining))
+                    });
+                    // keep working and offer again
+                    return work(producer, folder, kept_remaining);
                 }
-            } else {
-                self.steal();
+                EXPIRED => {
+                    offer.store(OPEN, Ordering::Relaxed);
+                    scope.spawn(make_offer);
+                }
+                _ => (),
             }
+            folder = producer.fold_block(folder);
         }
-        folder
+        folder.complete()
+    });
+    match thief_result.into_inner().unwrap() {
+        Some(thief_result) => reducer.reduce(worker_result, thief_result),
+        None => worker_result,
     }
 }
@@ -1,18 +1,241 @@
 use rayon::iter::plumbing::*;
 use rayon::iter::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+/// Sizes of successive blocks.
+///
+/// This is implemented by all iterators on sizes.
+/// Internally, timed and bounded blocks also use it to adapt sizes
+/// to the time taken by previous blocks or to stop early.
+pub trait BlockSizes: Send {
+    /// Size of the next block, `None` if all remaining items should form the last block.
+    fn next_size(&mut self) -> Option<usize>;
+    /// Called after each block with its size and the time taken to process it.
+    fn block_done(&mut self, _size: usize, _duration: Duration) {}
+    /// Return true if no more blocks should be processed.
+    fn stopped(&self) -> bool {
+        false
+    }
+}
+
+impl<S: Iterator<Item = usize> + Send> BlockSizes for S {
+    fn next_size(&mut self) -> Option<usize> {
+        self.next()
+    }
+}
+
+/// `ByBlocks` is a parallel iterator that consumes itself as a sequence
+/// of parallel blocks whose sizes are given by an iterator.
+///
+/// Blocks are only used when the iterator is driven by a consumer:
+/// positional adaptors like `zip` or `enumerate`, and searches like `position_first`,
+/// must come **before** blocking.
+///
+/// This struct is created by the [`by_blocks()`] method on [`IndexedParallelIterator`]
+/// [`by_blocks()`]: trait.IndexedParallelIterator.html#method.by_blocks
+/// [`IndexedParallelIterator`]: trait.IndexedParallelIterator.html
 #[must_use = "iterator adaptors are lazy and do nothing unless consumed"]
 #[derive(Debug, Clone)]
-struct ByBlocks<I, S> {
+pub struct ByBlocks<I, S> {
     base: I,
     sizes: S,
 }
 
 impl<I, S> ByBlocks<I, S> {
     /// Creates a new `ByBlocks` iterator.
-    pub(super) fn new(base: I, sizes: S) -> Self {
+    pub(crate) fn new(base: I, sizes: S) -> Self {
         ByBlocks { base, sizes }
     }
+
+    /// Stop starting new blocks once `deadline` is passed.
+    /// See [`Bounded`].
+    ///
+    /// [`Bounded`]: struct.Bounded.html
+    pub fn until_deadline(self, deadline: Instant) -> Bounded<I, S> {
+        Bounded::new(self).until_deadline(deadline)
+    }
+
+    /// Stop starting new blocks once `items` items were given to previous blocks.
+    /// See [`Bounded`].
+    ///
+    /// [`Bounded`]: struct.Bounded.html
+    pub fn with_budget(self, items: usize) -> Bounded<I, S> {
+        Bounded::new(self).with_budget(items)
+    }
+}
+
+impl<I, S> ByBlocks<I, S>
+where
+    I: IndexedParallelIterator,
+    S: BlockSizes,
+{
+    /// Fold all blocks sequentially, each block being processed in parallel.
+    ///
+    /// Inside a block, each item is turned into a value by `block_op`,
+    /// which also receives the value accumulated over all previous blocks
+    /// (starting with `init`).
+    /// These values are reduced with `combine` and the result
+    /// is then combined with the accumulated value before moving on to the next block.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// // count items larger than the maximum of all previous blocks
+    /// let v: Vec<u32> = (0..10_000).map(|i| (i * 7_919) % 10_007).collect();
+    /// let (max, records) = v
+    ///     .par_iter()
+    ///     .by_uniform_blocks(1_000)
+    ///     .fold_blocks(
+    ///         (0, 0),
+    ///         |&(max, _), &e| if e > max { (e, 1) } else { (max, 0) },
+    ///         |(max1, r1), (max2, r2)| (max1.max(max2), r1 + r2),
+    ///     );
+    /// assert_eq!(max, *v.iter().max().unwrap());
+    /// assert!(records > 0);
+    /// ```
+    pub fn fold_blocks<T, OP, C>(self, init: T, block_op: OP, combine: C) -> T
+    where
+        T: Send + Sync,
+        OP: Fn(&T, I::Item) -> T + Sync + Send,
+        C: Fn(T, T) -> T + Sync + Send,
+    {
+        let len = self.base.len();
+        self.base.with_producer(FoldBlocksCallback {
+            sizes: self.sizes,
+            len,
+            init,
+            block_op,
+            combine,
+        })
+    }
+}
+
+struct FoldBlocksCallback<S, T, OP, C> {
+    sizes: S,
+    len: usize,
+    init: T,
+    block_op: OP,
+    combine: C,
+}
+
+impl<Item, S, T, OP, C> ProducerCallback<Item> for FoldBlocksCallback<S, T, OP, C>
+where
+    Item: Send,
+    S: BlockSizes,
+    T: Send + Sync,
+    OP: Fn(&T, Item) -> T + Sync + Send,
+    C: Fn(T, T) -> T + Sync + Send,
+{
+    type Output = T;
+
+    fn callback<P: Producer<Item = Item>>(self, mut producer: P) -> Self::Output {
+        let mut schedule = BlockSchedule::new(self.sizes, self.len);
+        let mut accumulated = self.init;
+        let block_op = &self.block_op;
+        let combine = &self.combine;
+        while !schedule.done() {
+            let size = schedule.next_size();
+            let (left_producer, right_producer) = producer.split_at(size);
+            producer = right_producer;
+            let previous = &accumulated;
+            let block_res = schedule.run(size, || {
+                Block {
+                    producer: left_producer,
+                    len: size,
+                }
+                .map(|e| block_op(previous, e))
+                .reduce_with(combine)
+            });
+            if let Some(block_res) = block_res {
+                accumulated = combine(accumulated, block_res);
+            }
+        }
+        accumulated
+    }
+}
+
+/// Sizes of the successive blocks of a given number of items.
+struct BlockSchedule<S> {
+    sizes: S,
+    remaining_len: usize,
+}
+
+impl<S: BlockSizes> BlockSchedule<S> {
+    fn new(sizes: S, len: usize) -> Self {
+        BlockSchedule {
+            sizes,
+            remaining_len: len,
+        }
+    }
+
+    /// Return true if no more blocks should be processed.
+    fn done(&self) -> bool {
+        // sizes are only asked to stop when there is work left
+        self.remaining_len == 0 || self.sizes.stopped()
+    }
+
+    /// Size of the next block, which is never empty since empty blocks would never end.
+    fn next_size(&mut self) -> usize {
+        let size = self.sizes.next_size().unwrap_or(usize::MAX).max(1);
+        let capped_size = self.remaining_len.min(size);
+        self.remaining_len -= capped_size;
+        capped_size
+    }
+
+    /// Process a block of given size with `op`, reporting its duration to the sizes.
+    fn run<R>(&mut self, size: usize, op: impl FnOnce() -> R) -> R {
+        let start = Instant::now();
+        let result = op();
+        self.sizes.block_done(size, start.elapsed());
+        result
+    }
+}
+
+/// Parallel iterator on the items of one block.
+struct Block<P> {
+    producer: P,
+    len: usize,
+}
+
+impl<P> ParallelIterator for Block<P>
+where
+    P: Producer,
+    P::Item: Send,
+{
+    type Item = P::Item;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        self.drive(consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+impl<P> IndexedParallelIterator for Block<P>
+where
+    P: Producer,
+    P::Item: Send,
+{
+    fn drive<C: Consumer<Self::Item>>(self, consumer: C) -> C::Result {
+        bridge_producer_consumer(self.len, self.producer, consumer)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn with_producer<CB: ProducerCallback<Self::Item>>(self, callback: CB) -> CB::Output {
+        callback.callback(self.producer)
+    }
 }
 
 struct BlocksCallback<S, C> {
@@ -23,13 +246,13 @@ struct BlocksCallback<S, C> {
 
 impl<T, S, C> ProducerCallback<T> for BlocksCallback<S, C>
 where
-    C: UnindexedConsumer<T>,
-    S: Iterator<Item = usize>,
+    C: Consumer<T>,
+    S: BlockSizes,
 {
     type Output = C::Result;
 
-    fn callback<P: Producer<Item = T>>(mut self, mut producer: P) -> Self::Output {
-        let mut remaining_len = self.len;
+    fn callback<P: Producer<Item = T>>(self, mut producer: P) -> Self::Output {
+        let mut schedule = BlockSchedule::new(self.sizes, self.len);
         let mut consumer = self.consumer;
         // we need a local variable for the accumulated results
         // we call the reducer's identity by splitting at 0
@@ -38,22 +261,19 @@ where
         consumer = right_consumer;
 
         // now we loop on each block size
-        while remaining_len > 0 && !consumer.full() {
-            // we compute the next block's size
-            let size = self.sizes.next().unwrap_or(std::usize::MAX);
-            let capped_size = remaining_len.min(size);
-            remaining_len -= capped_size;
+        while !consumer.full() && !schedule.done() {
+            let size = schedule.next_size();
 
             // split the producer
-            let (left_producer, right_producer) = producer.split_at(capped_size);
+            let (left_producer, right_producer) = producer.split_at(size);
             producer = right_producer;
             // split the consumer
-            let (left_consumer, right_consumer, _) = consumer.split_at(capped_size);
+            let (left_consumer, right_consumer, reducer) = consumer.split_at(size);
             consumer = right_consumer;
-            leftmost_res = consumer.to_reducer().reduce(
-                leftmost_res,
-                bridge_producer_consumer(capped_size, left_producer, left_consumer),
-            );
+            let block_res = schedule.run(size, || {
+                bridge_producer_consumer(size, left_producer, left_consumer)
+            });
+            leftmost_res = reducer.reduce(leftmost_res, block_res);
         }
         leftmost_res
     }
@@ -62,7 +282,7 @@ where
 impl<I, S> ParallelIterator for ByBlocks<I, S>
 where
     I: IndexedParallelIterator,
-    S: Iterator<Item = usize> + Send,
+    S: BlockSizes,
 {
     type Item = I::Item;
 
@@ -70,6 +290,23 @@ where
     where
         C: UnindexedConsumer<Self::Item>,
     {
+        self.drive(consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.base.len())
+    }
+}
+
+/// Blocks are only used when the iterator is driven by a consumer.
+/// When a producer is requested (for example by `zip`) the base producer
+/// is used as is and blocking is lost.
+impl<I, S> IndexedParallelIterator for ByBlocks<I, S>
+where
+    I: IndexedParallelIterator,
+    S: BlockSizes,
+{
+    fn drive<C: Consumer<Self::Item>>(self, consumer: C) -> C::Result {
         let len = self.base.len();
         let callback = BlocksCallback {
             consumer,
@@ -78,31 +315,133 @@ where
         };
         self.base.with_producer(callback)
     }
+
+    fn len(&self) -> usize {
+        self.base.len()
+    }
+
+    fn with_producer<CB: ProducerCallback<Self::Item>>(self, callback: CB) -> CB::Output {
+        // positional adaptors divide the base producer as usual: blocks are lost
+        self.base.with_producer(callback)
+    }
 }
 
 /// `ExponentialBlocks` is a parallel iterator that consumes itself as a sequence
 /// of parallel blocks of increasing sizes (exponentially).
 ///
-/// This struct is created by the [`by_exponential_blocks()`] method on [`IndexedParallelIterator`]
+/// This type is created by the [`by_exponential_blocks()`] method on [`IndexedParallelIterator`]
 /// [`by_exponential_blocks()`]: trait.IndexedParallelIterator.html#method.by_exponential_blocks
 /// [`IndexedParallelIterator`]: trait.IndexedParallelIterator.html
+pub type ExponentialBlocks<I> = ByBlocks<I, ExponentialSizes>;
+
+impl<I> ExponentialBlocks<I> {
+    /// Cap the size of all blocks to `max` items.
+    pub fn max_block_size(mut self, max: usize) -> Self {
+        self.sizes.max = max;
+        self
+    }
+}
+
+/// Sizes of exponential blocks, multiplied by `factor` at each step.
+#[derive(Debug, Clone)]
+pub struct ExponentialSizes {
+    size: f64,
+    factor: f64,
+    max: usize,
+}
+
+impl ExponentialSizes {
+    pub(crate) fn new(first: usize, factor: f64) -> Self {
+        assert!(factor >= 1.0, "blocks sizes cannot decrease");
+        ExponentialSizes {
+            size: first.max(1) as f64,
+            factor,
+            max: usize::MAX,
+        }
+    }
+}
+
+impl Iterator for ExponentialSizes {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let size = (self.size.round() as usize).clamp(1, self.max.max(1));
+        self.size *= self.factor;
+        Some(size)
+    }
+}
+
+/// `ExponentialBlocksRev` is a parallel iterator that consumes itself as a sequence
+/// of parallel blocks of increasing sizes (exponentially), starting from the end.
+///
+/// Like for [`ByBlocks`], positional adaptors must come before blocking.
+///
+/// This struct is created by the [`by_exponential_blocks_rev()`] method on [`IndexedParallelIterator`]
+/// [`by_exponential_blocks_rev()`]: trait.IndexedParallelIterator.html#method.by_exponential_blocks_rev
+/// [`IndexedParallelIterator`]: trait.IndexedParallelIterator.html
+/// [`ByBlocks`]: struct.ByBlocks.html
 #[must_use = "iterator adaptors are lazy and do nothing unless consumed"]
 #[derive(Debug, Clone)]
-pub struct ExponentialBlocks<I>(
-    ByBlocks<I, std::iter::Successors<usize, fn(&usize) -> Option<usize>>>,
-);
+pub struct ExponentialBlocksRev<I> {
+    base: I,
+    sizes: ExponentialSizes,
+}
 
-impl<I> ExponentialBlocks<I> {
+impl<I> ExponentialBlocksRev<I> {
     pub(crate) fn new(base: I) -> Self {
-        let first = rayon::current_num_threads();
-        ExponentialBlocks(ByBlocks::new(
+        ExponentialBlocksRev {
             base,
-            std::iter::successors(Some(first), |s| Some(s.saturating_mul(2))),
-        ))
+            sizes: ExponentialSizes::new(rayon::current_num_threads(), 2.0),
+        }
+    }
+
+    /// Cap the size of all blocks to `max` items.
+    pub fn max_block_size(mut self, max: usize) -> Self {
+        self.sizes.max = max;
+        self
     }
 }
 
-impl<I> ParallelIterator for ExponentialBlocks<I>
+struct RevBlocksCallback<S, C> {
+    sizes: S,
+    consumer: C,
+    len: usize,
+}
+
+impl<T, S, C> ProducerCallback<T> for RevBlocksCallback<S, C>
+where
+    C: Consumer<T>,
+    S: BlockSizes,
+{
+    type Output = C::Result;
+
+    fn callback<P: Producer<Item = T>>(self, mut producer: P) -> Self::Output {
+        let mut schedule = BlockSchedule::new(self.sizes, self.len);
+        let mut consumer = self.consumer;
+        // the rightmost results start with the reducer's identity
+        let (left_consumer, right_consumer, _) = consumer.split_at(self.len);
+        let mut rightmost_res = right_consumer.into_folder().complete();
+        consumer = left_consumer;
+
+        // blocks are now taken from the end
+        while !consumer.full() && !schedule.done() {
+            let size = schedule.next_size();
+            let remaining_len = schedule.remaining_len;
+
+            let (left_producer, right_producer) = producer.split_at(remaining_len);
+            producer = left_producer;
+            let (left_consumer, right_consumer, reducer) = consumer.split_at(remaining_len);
+            consumer = left_consumer;
+            let block_res = schedule.run(size, || {
+                bridge_producer_consumer(size, right_producer, right_consumer)
+            });
+            rightmost_res = reducer.reduce(block_res, rightmost_res);
+        }
+        rightmost_res
+    }
+}
+
+impl<I> ParallelIterator for ExponentialBlocksRev<I>
 where
     I: IndexedParallelIterator,
 {
@@ -112,29 +451,273 @@ where
     where
         C: UnindexedConsumer<Self::Item>,
     {
-        self.0.drive_unindexed(consumer)
+        self.drive(consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.base.len())
+    }
+}
+
+impl<I> IndexedParallelIterator for ExponentialBlocksRev<I>
+where
+    I: IndexedParallelIterator,
+{
+    fn drive<C: Consumer<Self::Item>>(self, consumer: C) -> C::Result {
+        let len = self.base.len();
+        let callback = RevBlocksCallback {
+            consumer,
+            sizes: self.sizes,
+            len,
+        };
+        self.base.with_producer(callback)
+    }
+
+    fn len(&self) -> usize {
+        self.base.len()
+    }
+
+    fn with_producer<CB: ProducerCallback<Self::Item>>(self, callback: CB) -> CB::Output {
+        // positional adaptors divide the base producer as usual: blocks are lost
+        self.base.with_producer(callback)
     }
 }
 
 /// `UniformBlocks` is a parallel iterator that consumes itself as a sequence
 /// of parallel blocks of constant sizes.
 ///
-/// This struct is created by the [`by_uniform_blocks()`] method on [`IndexedParallelIterator`]
+/// This type is created by the [`by_uniform_blocks()`] method on [`IndexedParallelIterator`]
 /// [`by_uniform_blocks()`]: trait.IndexedParallelIterator.html#method.by_uniform_blocks
 /// [`IndexedParallelIterator`]: trait.IndexedParallelIterator.html
+pub type UniformBlocks<I> = ByBlocks<I, std::iter::Repeat<usize>>;
+
+/// `TimedBlocks` is a parallel iterator that consumes itself as a sequence
+/// of parallel blocks, each one taking roughly a given time.
+///
+/// This type is created by the [`by_timed_blocks()`] method on [`IndexedParallelIterator`]
+/// [`by_timed_blocks()`]: trait.IndexedParallelIterator.html#method.by_timed_blocks
+/// [`IndexedParallelIterator`]: trait.IndexedParallelIterator.html
+pub type TimedBlocks<I> = ByBlocks<I, TimedSizes>;
+
+/// Sizes of blocks targeting a given duration.
+#[derive(Debug, Clone)]
+pub struct TimedSizes {
+    target: Duration,
+    size: usize,
+}
+
+impl TimedSizes {
+    pub(crate) fn new(target: Duration) -> Self {
+        TimedSizes {
+            target,
+            size: rayon::current_num_threads(),
+        }
+    }
+}
+
+impl BlockSizes for TimedSizes {
+    fn next_size(&mut self) -> Option<usize> {
+        Some(self.size)
+    }
+
+    fn block_done(&mut self, size: usize, duration: Duration) {
+        // we extrapolate from the last block's speed but avoid growing too fast
+        // since timings on small blocks are not reliable
+        let ratio = if duration.as_nanos() == 0 {
+            2.0
+        } else {
+            (self.target.as_secs_f64() / duration.as_secs_f64()).min(2.0)
+        };
+        self.size = ((size as f64 * ratio) as usize).max(1);
+    }
+}
+
+/// `InspectBlocks` is a parallel iterator that consumes itself as a sequence
+/// of parallel blocks, calling a closure after each block.
+///
+/// Since the closure can stop the iteration, this iterator is not indexed.
+///
+/// This struct is created by the [`by_blocks_inspect()`] method on [`IndexedParallelIterator`]
+/// [`by_blocks_inspect()`]: trait.IndexedParallelIterator.html#method.by_blocks_inspect
+/// [`IndexedParallelIterator`]: trait.IndexedParallelIterator.html
+#[must_use = "iterator adaptors are lazy and do nothing unless consumed"]
+#[derive(Clone)]
+pub struct InspectBlocks<I, S, F>(ByBlocks<I, InspectSizes<S, F>>);
+
+impl<I, S, F> InspectBlocks<I, S, F> {
+    pub(crate) fn new(base: I, sizes: S, inspect: F) -> Self {
+        InspectBlocks(ByBlocks::new(
+            base,
+            InspectSizes {
+                sizes,
+                inspect,
+                index: 0,
+                start: 0,
+                stopped: false,
+            },
+        ))
+    }
+}
+
+impl<I, S, F> InspectBlocks<I, S, F>
+where
+    I: IndexedParallelIterator,
+    S: BlockSizes,
+    F: FnMut(usize, usize, usize) -> bool + Send,
+{
+    /// Fold all blocks sequentially, see [`ByBlocks::fold_blocks`].
+    ///
+    /// [`ByBlocks::fold_blocks`]: struct.ByBlocks.html#method.fold_blocks
+    pub fn fold_blocks<T, OP, C>(self, init: T, block_op: OP, combine: C) -> T
+    where
+        T: Send + Sync,
+        OP: Fn(&T, I::Item) -> T + Sync + Send,
+        C: Fn(T, T) -> T + Sync + Send,
+    {
+        self.0.fold_blocks(init, block_op, combine)
+    }
+}
+
+impl<I, S, F> ParallelIterator for InspectBlocks<I, S, F>
+where
+    I: IndexedParallelIterator,
+    S: BlockSizes,
+    F: FnMut(usize, usize, usize) -> bool + Send,
+{
+    type Item = I::Item;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        self.0.drive_unindexed(consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        // `inspect` can stop us early
+        None
+    }
+}
+
+/// Sizes of blocks, calling `inspect` after each block.
+#[derive(Clone)]
+struct InspectSizes<S, F> {
+    sizes: S,
+    inspect: F,
+    index: usize,
+    start: usize,
+    stopped: bool,
+}
+
+impl<S, F> BlockSizes for InspectSizes<S, F>
+where
+    S: BlockSizes,
+    F: FnMut(usize, usize, usize) -> bool + Send,
+{
+    fn next_size(&mut self) -> Option<usize> {
+        self.sizes.next_size()
+    }
+
+    fn block_done(&mut self, size: usize, duration: Duration) {
+        self.sizes.block_done(size, duration);
+        self.stopped = !(self.inspect)(self.index, self.start, size);
+        self.index += 1;
+        self.start += size;
+    }
+
+    fn stopped(&self) -> bool {
+        self.stopped || self.sizes.stopped()
+    }
+}
+
+/// `Bounded` is a parallel iterator consuming itself by blocks and
+/// stopping early once a deadline is passed or a budget of items is used up.
+///
+/// A running block is never interrupted: the bounds are checked before starting each block.
+/// Use [`with_completion()`] to know if the bounds were reached.
+/// Since the number of processed items is not known in advance, this iterator is not indexed.
+///
+/// This struct is created by the `until_deadline()` and `with_budget()` methods
+/// of the blocks adaptors.
+///
+/// [`with_completion()`]: #method.with_completion
 #[must_use = "iterator adaptors are lazy and do nothing unless consumed"]
 #[derive(Debug, Clone)]
-pub struct UniformBlocks<I>(ByBlocks<I, std::iter::Repeat<usize>>);
+pub struct Bounded<I, S>(ByBlocks<I, BoundedSizes<S>>);
 
-impl<I> UniformBlocks<I> {
-    pub(crate) fn new(base: I, blocks_size: usize) -> Self {
-        UniformBlocks(ByBlocks::new(base, std::iter::repeat(blocks_size)))
+impl<I, S> Bounded<I, S> {
+    fn new(blocks: ByBlocks<I, S>) -> Self {
+        Bounded(ByBlocks::new(
+            blocks.base,
+            BoundedSizes {
+                sizes: blocks.sizes,
+                deadline: None,
+                budget: None,
+                interrupted: Arc::new(AtomicBool::new(false)),
+            },
+        ))
+    }
+
+    /// Stop starting new blocks once `deadline` is passed.
+    pub fn until_deadline(mut self, deadline: Instant) -> Self {
+        self.0.sizes.deadline = Some(deadline);
+        self
+    }
+
+    /// Stop starting new blocks once `items` items were given to previous blocks.
+    /// Blocks are shortened in order to never process more than `items` items.
+    pub fn with_budget(mut self, items: usize) -> Self {
+        self.0.sizes.budget = Some(items);
+        self
+    }
+
+    /// Run `op` on this iterator, also returning `false` if the computation
+    /// was stopped early by the deadline or the budget.
+    ///
+    /// `true` only means the bounds did not cut the computation off:
+    /// it does not mean that the whole input was processed, since `op` can
+    /// stop early on its own (with `find_first` for example).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// use std::time::{Duration, Instant};
+    /// let bounded = (0..10_000u32).into_par_iter().by_uniform_blocks(1_000).with_budget(2_500);
+    /// assert_eq!(bounded.with_completion(|i| i.count()), (2_500, false));
+    /// let bounded = (0..10_000u32).into_par_iter().by_uniform_blocks(1_000).with_budget(2_500);
+    /// let (v, completed) = bounded.with_completion(|i| i.collect::<Vec<_>>());
+    /// assert_eq!(v, (0..2_500).collect::<Vec<_>>());
+    /// assert!(!completed);
+    ///
+    /// // best result within 50ms
+    /// let deadline = Instant::now() + Duration::from_millis(50);
+    /// let (best, completed) = (0..1_000_000u32)
+    ///     .into_par_iter()
+    ///     .by_timed_blocks(Duration::from_millis(5))
+    ///     .until_deadline(deadline)
+    ///     .with_completion(|i| i.map(|e| e % 1_013).max());
+    /// assert!(!completed || best == Some(1_012));
+    ///
+    /// // searches stop on their own, without being cut off
+    /// let bounded = (0..10_000u32).into_par_iter().by_uniform_blocks(1_000).with_budget(5_000);
+    /// assert_eq!(bounded.with_completion(|i| i.find_first(|&e| e == 10)), (Some(10), true));
+    /// ```
+    pub fn with_completion<R, OP>(self, op: OP) -> (R, bool)
+    where
+        OP: FnOnce(Self) -> R,
+    {
+        let interrupted = self.0.sizes.interrupted.clone();
+        let result = op(self);
+        (result, !interrupted.load(Ordering::Relaxed))
     }
 }
 
-impl<I> ParallelIterator for UniformBlocks<I>
+impl<I, S> ParallelIterator for Bounded<I, S>
 where
     I: IndexedParallelIterator,
+    S: BlockSizes,
 {
     type Item = I::Item;
 
@@ -144,4 +727,49 @@ where
     {
         self.0.drive_unindexed(consumer)
     }
+
+    fn opt_len(&self) -> Option<usize> {
+        // we do not know in advance where we stop
+        None
+    }
+}
+
+/// Sizes of blocks, stopping after a deadline or a number of items.
+#[derive(Debug, Clone)]
+struct BoundedSizes<S> {
+    sizes: S,
+    deadline: Option<Instant>,
+    /// Remaining number of items we can process.
+    budget: Option<usize>,
+    /// Set if we stopped before the end.
+    interrupted: Arc<AtomicBool>,
+}
+
+impl<S: BlockSizes> BlockSizes for BoundedSizes<S> {
+    fn next_size(&mut self) -> Option<usize> {
+        let size = self.sizes.next_size();
+        match self.budget {
+            Some(budget) => Some(size.unwrap_or(usize::MAX).min(budget)),
+            None => size,
+        }
+    }
+
+    fn block_done(&mut self, size: usize, duration: Duration) {
+        self.sizes.block_done(size, duration);
+        if let Some(budget) = self.budget.as_mut() {
+            *budget = budget.saturating_sub(size);
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        // this is only called when there is work left
+        let out_of_time = self.deadline.is_some_and(|d| Instant::now() >= d);
+        let out_of_items = self.budget == Some(0);
+        if out_of_time || out_of_items {
+            self.interrupted.store(true, Ordering::Relaxed);
+            true
+        } else {
+            self.sizes.stopped()
+        }
+    }
 }
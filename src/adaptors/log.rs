@@ -1,23 +1,29 @@
 //! Provides logging for parallel iterators.
 use rayon::iter::plumbing::*;
 use rayon::iter::*;
+use std::borrow::Cow;
 use std::cell::Cell;
+use std::sync::Arc;
 use tracing::span::{EnteredSpan, Id};
+use tracing::Span;
 
 /// `Logged` is an iterator that logs all tasks created.
 #[must_use = "iterator adaptors are lazy and do nothing unless consumed"]
 pub struct Logged<I: ParallelIterator> {
     base: I,
-    tag: &'static str,
+    tag: Cow<'static, str>,
 }
 
 impl<I: ParallelIterator> Logged<I> {
     /// Create a new `Logged` iterator.
-    pub(crate) fn new(base: I, tag: &'static str) -> Logged<I>
+    pub(crate) fn new(base: I, tag: impl Into<Cow<'static, str>>) -> Logged<I>
     where
         I: ParallelIterator,
     {
-        Logged { base, tag }
+        Logged {
+            base,
+            tag: tag.into(),
+        }
     }
 }
 
@@ -38,7 +44,7 @@ where
             left: false,
             base: consumer,
             father_id: Cell::new(father_id.map(|id| id.into_u64())),
-            tag: self.tag,
+            tag: &self.tag,
         };
         let _enter = start_span.enter();
         self.base.drive_unindexed(logged_consumer)
@@ -49,46 +55,269 @@ where
     }
 }
 
-struct LoggedReducer<R> {
+impl<T, I> IndexedParallelIterator for Logged<I>
+where
+    I: IndexedParallelIterator<Item = T>,
+    T: Send,
+{
+    fn len(&self) -> usize {
+        self.base.len()
+    }
+
+    fn drive<C: Consumer<Self::Item>>(self, consumer: C) -> C::Result {
+        let start_span = tracing::span!(tracing::Level::TRACE, "drive");
+        let father_id = start_span.id();
+        let logged_consumer = LoggedConsumer {
+            left: false,
+            base: consumer,
+            father_id: Cell::new(father_id.map(|id| id.into_u64())),
+            tag: &self.tag,
+        };
+        let _enter = start_span.enter();
+        self.base.drive(logged_consumer)
+    }
+
+    fn with_producer<CB: ProducerCallback<Self::Item>>(self, callback: CB) -> CB::Output {
+        let start_span = tracing::span!(tracing::Level::TRACE, "drive");
+        let _enter = start_span.enter();
+        let father = Arc::new(TaskSpans {
+            parallel: start_span.clone(),
+            sequential: Span::none(),
+            father: None,
+        });
+        return self.base.with_producer(Callback {
+            callback,
+            father,
+            tag: &self.tag,
+        });
+
+        struct Callback<'t, CB> {
+            callback: CB,
+            father: Arc<TaskSpans>,
+            tag: &'t str,
+        }
+
+        impl<'t, T, CB> ProducerCallback<T> for Callback<'t, CB>
+        where
+            CB: ProducerCallback<T>,
+        {
+            type Output = CB::Output;
+            fn callback<P>(self, base: P) -> CB::Output
+            where
+                P: Producer<Item = T>,
+            {
+                let producer = LoggedProducer {
+                    base,
+                    left: false,
+                    father: self.father,
+                    tag: self.tag,
+                };
+                self.callback.callback(producer)
+            }
+        }
+    }
+}
+
+struct LoggedReducer<'t, R> {
     base: R,
     par_span: EnteredSpan,
-    _seq_span: EnteredSpan,
+    seq_span: EnteredSpan,
+    tag: &'t str,
 }
 
-impl<Result, R: Reducer<Result>> Reducer<Result> for LoggedReducer<R> {
+impl<'t, Result, R: Reducer<Result>> Reducer<Result> for LoggedReducer<'t, R> {
     fn reduce(self, left: Result, right: Result) -> Result {
         std::mem::drop(self.par_span);
+        let reduce_span = tracing::span!(parent: self.seq_span.id(), tracing::Level::TRACE, "reduce", label = self.tag);
+        let _enter = reduce_span.enter();
         self.base.reduce(left, right)
     }
 }
 
+// ////////////////////////////////////////////////////////////////////////
+// Producer implementation
+
+/// Spans of a task which has been divided.
+/// They are shared by all sub-tasks and close once the last of them is done.
+struct TaskSpans {
+    parallel: Span,
+    sequential: Span,
+    father: Option<Arc<TaskSpans>>,
+}
+
+impl Drop for TaskSpans {
+    fn drop(&mut self) {
+        // close our spans before our ancestors' ones
+        self.parallel = Span::none();
+        self.sequential = Span::none();
+        // sequential splits chain fathers over long distances:
+        // unlink them in a loop instead of recursing.
+        let mut father = self.father.take();
+        while let Some(mut spans) = father.and_then(|f| Arc::try_unwrap(f).ok()) {
+            father = spans.father.take();
+        }
+    }
+}
+
+struct LoggedProducer<'t, P> {
+    base: P,
+    left: bool,
+    father: Arc<TaskSpans>,
+    tag: &'t str,
+}
+
+impl<'t, P: Producer> Producer for LoggedProducer<'t, P> {
+    type Item = P::Item;
+    type IntoIter = LoggedIter<P::IntoIter>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let (outer_span, span) = enter_fold_spans(self.left, self.father.parallel.id(), self.tag);
+        LoggedIter {
+            base: self.base.into_iter(),
+            items: 0,
+            span,
+            _outer_span: outer_span,
+        }
+    }
+
+    fn min_len(&self) -> usize {
+        self.base.min_len()
+    }
+
+    fn max_len(&self) -> usize {
+        self.base.max_len()
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let sequential_span = sequential_span(self.left, self.father.parallel.id());
+        let parallel_span =
+            tracing::span!(parent: sequential_span.id(), tracing::Level::TRACE, "parallel", index);
+        let (left, right) = self.base.split_at(index);
+        let father = Arc::new(TaskSpans {
+            parallel: parallel_span,
+            sequential: sequential_span,
+            father: Some(self.father),
+        });
+        (
+            LoggedProducer {
+                base: left,
+                left: true,
+                father: father.clone(),
+                tag: self.tag,
+            },
+            LoggedProducer {
+                base: right,
+                left: false,
+                father,
+                tag: self.tag,
+            },
+        )
+    }
+
+    fn fold_with<F>(self, folder: F) -> F
+    where
+        F: Folder<Self::Item>,
+    {
+        let (outer_span, span) = enter_fold_spans(self.left, self.father.parallel.id(), self.tag);
+        let folder = LoggedFolder {
+            base: folder,
+            items: 0,
+            span,
+            outer_span,
+        };
+        self.base.fold_with(folder).into_base()
+    }
+}
+
+/// Sequential iterator of a `LoggedProducer`.
+/// The task's spans are closed when it is dropped.
+struct LoggedIter<I> {
+    base: I,
+    items: u64,
+    span: EnteredSpan,
+    _outer_span: EnteredSpan,
+}
+
+impl<I> Drop for LoggedIter<I> {
+    fn drop(&mut self) {
+        self.span.record("items", self.items);
+    }
+}
+
+impl<I: Iterator> Iterator for LoggedIter<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.base.next();
+        self.items += next.is_some() as u64;
+        next
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.base.size_hint()
+    }
+}
+
+impl<I: DoubleEndedIterator> DoubleEndedIterator for LoggedIter<I> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let next = self.base.next_back();
+        self.items += next.is_some() as u64;
+        next
+    }
+}
+
+impl<I: ExactSizeIterator> ExactSizeIterator for LoggedIter<I> {
+    fn len(&self) -> usize {
+        self.base.len()
+    }
+}
+
+/// Create the span of a sequential task, attached to its father's "parallel" span.
+fn sequential_span(left: bool, father_id: Option<Id>) -> Span {
+    if left {
+        tracing::span!(parent: father_id, tracing::Level::TRACE, "left")
+    } else {
+        tracing::span!(parent: father_id, tracing::Level::TRACE, "right")
+    }
+}
+
+/// Create and enter the spans of a sequential task folding items.
+/// Return the task's span and the "fold" span.
+fn enter_fold_spans(left: bool, father_id: Option<Id>, tag: &str) -> (EnteredSpan, EnteredSpan) {
+    let sequential_span = sequential_span(left, father_id);
+    let folder_span = tracing::span!(
+        parent: sequential_span.id(),
+        tracing::Level::TRACE,
+        "fold",
+        label = tag,
+        items = tracing::field::Empty
+    );
+    (sequential_span.entered(), folder_span.entered())
+}
+
 /// ////////////////////////////////////////////////////////////////////////
 /// Consumer implementation
 
-struct LoggedConsumer<C> {
+struct LoggedConsumer<'t, C> {
     base: C,
     left: bool,
     father_id: Cell<Option<u64>>,
-    tag: &'static str,
+    tag: &'t str,
 }
 
-impl<T, C> Consumer<T> for LoggedConsumer<C>
+impl<'t, T, C> Consumer<T> for LoggedConsumer<'t, C>
 where
     C: Consumer<T>,
     T: Send,
 {
     type Folder = LoggedFolder<C::Folder>;
-    type Reducer = LoggedReducer<C::Reducer>;
+    type Reducer = LoggedReducer<'t, C::Reducer>;
     type Result = C::Result;
 
     fn split_at(self, index: usize) -> (Self, Self, Self::Reducer) {
-        let sequential_span = if self.left {
-            tracing::span!(parent: self.father_id.get().map(Id::from_u64), tracing::Level::TRACE, "left")
-        } else {
-            tracing::span!(parent: self.father_id.get().map(Id::from_u64), tracing::Level::TRACE, "right")
-        };
+        let sequential_span = sequential_span(self.left, self.father_id.get().map(Id::from_u64));
         let parallel_span =
-            tracing::span!(parent: sequential_span.id(), tracing::Level::TRACE, "parallel");
+            tracing::span!(parent: sequential_span.id(), tracing::Level::TRACE, "parallel", index);
         let entered_sequential_span = sequential_span.entered();
         let (left, right, reducer) = self.base.split_at(index);
         (
@@ -106,24 +335,21 @@ where
             },
             LoggedReducer {
                 base: reducer,
-                _seq_span: entered_sequential_span,
+                seq_span: entered_sequential_span,
                 par_span: parallel_span.entered(),
+                tag: self.tag,
             },
         )
     }
 
     fn into_folder(self) -> LoggedFolder<C::Folder> {
-        let sequential_span = if self.left {
-            tracing::span!(parent: self.father_id.get().map(Id::from_u64), tracing::Level::TRACE, "left")
-        } else {
-            tracing::span!(parent: self.father_id.get().map(Id::from_u64), tracing::Level::TRACE, "right")
-        };
-        let folder_span = tracing::span!(parent: sequential_span.id(), tracing::Level::TRACE, "fold", label=self.tag);
-        let entered_seq = sequential_span.entered();
+        let (outer_span, span) =
+            enter_fold_spans(self.left, self.father_id.get().map(Id::from_u64), self.tag);
         LoggedFolder {
             base: self.base.into_folder(),
-            span: folder_span.entered(),
-            outer_span: entered_seq,
+            items: 0,
+            span,
+            outer_span,
         }
     }
 
@@ -132,7 +358,7 @@ where
     }
 }
 
-impl<T, C> UnindexedConsumer<T> for LoggedConsumer<C>
+impl<'t, T, C> UnindexedConsumer<T> for LoggedConsumer<'t, C>
 where
     C: UnindexedConsumer<T>,
     T: Send,
@@ -145,12 +371,8 @@ where
             tag: self.tag,
         }
     }
-    fn to_reducer(&self) -> LoggedReducer<C::Reducer> {
-        let sequential_span = if self.left {
-            tracing::span!(parent: self.father_id.get().map(Id::from_u64), tracing::Level::TRACE, "left")
-        } else {
-            tracing::span!(parent: self.father_id.get().map(Id::from_u64), tracing::Level::TRACE, "right")
-        };
+    fn to_reducer(&self) -> LoggedReducer<'t, C::Reducer> {
+        let sequential_span = sequential_span(self.left, self.father_id.get().map(Id::from_u64));
         let entered_sequential_span = sequential_span.entered();
         let parallel_span =
             tracing::span!(parent: entered_sequential_span.id(), tracing::Level::TRACE, "parallel");
@@ -158,8 +380,9 @@ where
             .set(parallel_span.id().map(|id| id.into_u64()));
         LoggedReducer {
             base: self.base.to_reducer(),
-            _seq_span: entered_sequential_span,
+            seq_span: entered_sequential_span,
             par_span: parallel_span.entered(),
+            tag: self.tag,
         }
     }
 }
@@ -169,27 +392,52 @@ where
 
 struct LoggedFolder<F> {
     base: F,
+    items: u64,
     span: EnteredSpan,
     outer_span: EnteredSpan,
 }
 
+impl<F> LoggedFolder<F> {
+    /// Record the number of consumed items and close the spans.
+    fn into_base(self) -> F {
+        self.span.record("items", self.items);
+        self.base
+    }
+}
+
 impl<T, F> Folder<T> for LoggedFolder<F>
 where
     F: Folder<T>,
-    T: Send,
 {
     type Result = F::Result;
 
     fn consume(self, item: T) -> Self {
         LoggedFolder {
             base: self.base.consume(item),
+            items: self.items + 1,
+            span: self.span,
+            outer_span: self.outer_span,
+        }
+    }
+
+    fn consume_iter<I>(self, iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut items = self.items;
+        let base = self
+            .base
+            .consume_iter(iter.into_iter().inspect(|_| items += 1));
+        LoggedFolder {
+            base,
+            items,
             span: self.span,
             outer_span: self.outer_span,
         }
     }
 
     fn complete(self) -> F::Result {
-        self.base.complete()
+        self.into_base().complete()
     }
 
     fn full(&self) -> bool {
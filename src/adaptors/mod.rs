@@ -1,16 +1,28 @@
+#[cfg(feature = "logging")]
 mod log;
+#[cfg(feature = "logging")]
 pub use log::Logged;
+#[cfg(not(feature = "logging"))]
+mod no_log;
+#[cfg(not(feature = "logging"))]
+pub use no_log::Logged;
 mod scan;
 pub use scan::Scan;
 mod blocks;
-pub use blocks::{ExponentialBlocks, UniformBlocks};
+pub use blocks::{
+    BlockSizes, ByBlocks, ExponentialBlocks, ExponentialBlocksRev, ExponentialSizes, InspectBlocks,
+    TimedBlocks, TimedSizes, UniformBlocks,
+};
 mod tuples;
 pub use tuples::{HomogeneousTuples, Tuples};
+mod unindexed_blocks;
+pub use unindexed_blocks::{UnindexedBlocks, UnindexedProducerCallback, UnindexedSource};
 mod walk_tree;
 pub use walk_tree::{
-    walk_tree, walk_tree_postfix, walk_tree_prefix, WalkTree, WalkTreePostfix, WalkTreePrefix,
+    walk_tree, walk_tree_postfix, walk_tree_prefix, AdaptiveWalkTree, AdaptiveWalkTreePostfix,
+    AdaptiveWalkTreePrefix, WalkTree, WalkTreePostfix, WalkTreePrefix,
 };
 mod adaptive;
+pub use adaptive::{Adaptive, BlockPolicy};
 mod split_map;
 pub use split_map::SplitMap;
-// pub use adaptive::Adaptive;
@@ -0,0 +1,47 @@
+//! Pass-through replacement for logged iterators when the `logging` feature is disabled.
+use rayon::iter::plumbing::*;
+use rayon::iter::*;
+use std::borrow::Cow;
+
+/// `Logged` is an iterator that logs all tasks created.
+/// Since the `logging` feature is disabled it does nothing.
+#[must_use = "iterator adaptors are lazy and do nothing unless consumed"]
+pub struct Logged<I: ParallelIterator> {
+    base: I,
+}
+
+impl<I: ParallelIterator> Logged<I> {
+    /// Create a new `Logged` iterator.
+    pub(crate) fn new(base: I, _tag: impl Into<Cow<'static, str>>) -> Logged<I> {
+        Logged { base }
+    }
+}
+
+impl<I: ParallelIterator> ParallelIterator for Logged<I> {
+    type Item = I::Item;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        self.base.drive_unindexed(consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        self.base.opt_len()
+    }
+}
+
+impl<I: IndexedParallelIterator> IndexedParallelIterator for Logged<I> {
+    fn len(&self) -> usize {
+        self.base.len()
+    }
+
+    fn drive<C: Consumer<Self::Item>>(self, consumer: C) -> C::Result {
+        self.base.drive(consumer)
+    }
+
+    fn with_producer<CB: ProducerCallback<Self::Item>>(self, callback: CB) -> CB::Output {
+        self.base.with_producer(callback)
+    }
+}
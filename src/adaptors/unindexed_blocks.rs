@@ -0,0 +1,253 @@
+use rayon::iter::plumbing::*;
+use rayon::iter::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Pieces are never divided more than this number of times.
+const MAX_DEPTH: u32 = 32;
+
+/// Callback receiving the unindexed producer of a parallel iterator.
+///
+/// This is the unindexed counterpart to rayon's `ProducerCallback`.
+pub trait UnindexedProducerCallback<T> {
+    /// Type of value returned by the callback.
+    type Output;
+    /// Process the producer.
+    fn callback<P>(self, producer: P) -> Self::Output
+    where
+        P: UnindexedProducer<Item = T>;
+}
+
+/// Parallel iterators giving access to their unindexed producer.
+///
+/// Implementing this trait allows to process the iterator by blocks
+/// with [`by_unindexed_blocks()`].
+/// It is implemented by the tree walks: rayon's unindexed iterators
+/// do not expose their producers.
+///
+/// [`by_unindexed_blocks()`]: trait.DUnindexedParallelIterator.html#method.by_unindexed_blocks
+pub trait UnindexedSource: ParallelIterator {
+    /// Call `callback` on the producer of this iterator.
+    fn with_unindexed_producer<CB>(self, callback: CB) -> CB::Output
+    where
+        CB: UnindexedProducerCallback<Self::Item>;
+}
+
+/// `UnindexedBlocks` is a parallel iterator that consumes itself as a sequence
+/// of parallel blocks of roughly a given number of items.
+///
+/// This struct is created by the [`by_unindexed_blocks()`] method on [`DUnindexedParallelIterator`]
+/// [`by_unindexed_blocks()`]: trait.DUnindexedParallelIterator.html#method.by_unindexed_blocks
+/// [`DUnindexedParallelIterator`]: trait.DUnindexedParallelIterator.html
+#[must_use = "iterator adaptors are lazy and do nothing unless consumed"]
+#[derive(Debug, Clone)]
+pub struct UnindexedBlocks<I> {
+    base: I,
+    block_size: usize,
+}
+
+impl<I> UnindexedBlocks<I> {
+    pub(crate) fn new(base: I, block_size: usize) -> Self {
+        assert!(block_size > 0, "blocks cannot be empty");
+        UnindexedBlocks { base, block_size }
+    }
+}
+
+impl<I> ParallelIterator for UnindexedBlocks<I>
+where
+    I: UnindexedSource,
+{
+    type Item = I::Item;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        self.base.with_unindexed_producer(BlocksCallback {
+            block_size: self.block_size,
+            consumer,
+        })
+    }
+}
+
+struct BlocksCallback<C> {
+    block_size: usize,
+    consumer: C,
+}
+
+impl<T, C> UnindexedProducerCallback<T> for BlocksCallback<C>
+where
+    C: UnindexedConsumer<T>,
+{
+    type Output = C::Result;
+
+    fn callback<P>(self, producer: P) -> Self::Output
+    where
+        P: UnindexedProducer<Item = T>,
+    {
+        let consumer = self.consumer;
+        let mut blocks = Blocks {
+            pending: vec![(producer, 0)],
+            block_size: self.block_size as f64,
+            processed_items: 0,
+            processed_weight: 0.0,
+        };
+        // each call to split_off_left divides the positions available to the consumer.
+        // taking blocks one by one would exhaust them in a few dozen blocks
+        // so we take groups of blocks of doubling sizes, each group being split in a balanced way.
+        let mut result = blocks.run(1, consumer.split_off_left());
+        let mut group_size = 2;
+        while !blocks.pending.is_empty() && !consumer.full() {
+            let group_result = blocks.run(group_size, consumer.split_off_left());
+            result = consumer.to_reducer().reduce(result, group_result);
+            group_size = group_size.saturating_mul(2);
+        }
+        result
+    }
+}
+
+/// Division of a producer into blocks.
+struct Blocks<P> {
+    /// Pieces still to process, the leftmost one on top, with their splitting depths.
+    pending: Vec<(P, u32)>,
+    block_size: f64,
+    // we estimate the total number of items from what we already processed:
+    // a piece of depth d weighs 2^-d of the total.
+    processed_items: usize,
+    processed_weight: f64,
+}
+
+impl<P: UnindexedProducer> Blocks<P> {
+    /// Process up to `count` blocks with given consumer.
+    fn run<C: UnindexedConsumer<P::Item>>(&mut self, count: usize, consumer: C) -> C::Result {
+        if self.pending.is_empty() || consumer.full() {
+            consumer.into_folder().complete()
+        } else if count == 1 {
+            let (block, block_weight) = self.next_block();
+            let counter = AtomicUsize::new(0);
+            let result = bridge_unindexed(
+                Pieces {
+                    pieces: block,
+                    counter: &counter,
+                },
+                consumer,
+            );
+            self.processed_items += counter.into_inner();
+            self.processed_weight += block_weight;
+            result
+        } else {
+            let left_result = self.run(count / 2, consumer.split_off_left());
+            let right_result = self.run(count - count / 2, consumer.split_off_left());
+            consumer.to_reducer().reduce(left_result, right_result)
+        }
+    }
+
+    /// Take the pieces of the next block, with their total weight.
+    fn next_block(&mut self) -> (Vec<P>, f64) {
+        let mut block = Vec::new();
+        let mut block_weight = 0.0;
+        let mut block_estimate = 0.0;
+        while let Some((piece, depth)) = self.pending.pop() {
+            let weight = 0.5f64.powi(depth as i32);
+            // as long as we know nothing, we go for the smallest leftmost piece
+            let estimate = if self.processed_weight > 0.0 {
+                self.processed_items as f64 / self.processed_weight * weight
+            } else {
+                f64::INFINITY
+            };
+            if block.is_empty() && estimate > self.block_size && depth < MAX_DEPTH {
+                let (left, right) = piece.split();
+                if let Some(right) = right {
+                    self.pending.push((right, depth + 1));
+                    self.pending.push((left, depth + 1));
+                    continue;
+                }
+                // indivisible piece, it forms the block alone
+                block.push(left);
+                block_weight += weight;
+                break;
+            }
+            if !block.is_empty() && block_estimate + estimate > self.block_size {
+                self.pending.push((piece, depth));
+                break;
+            }
+            block.push(piece);
+            block_weight += weight;
+            block_estimate += estimate;
+        }
+        (block, block_weight)
+    }
+}
+
+/// Consecutive pieces of the initial producer, counting processed items.
+struct Pieces<'c, P> {
+    pieces: Vec<P>,
+    counter: &'c AtomicUsize,
+}
+
+impl<'c, P: UnindexedProducer> UnindexedProducer for Pieces<'c, P> {
+    type Item = P::Item;
+
+    fn split(mut self) -> (Self, Option<Self>) {
+        if self.pieces.len() > 1 {
+            let right_pieces = self.pieces.split_off(self.pieces.len() / 2);
+            let right = Pieces {
+                pieces: right_pieces,
+                counter: self.counter,
+            };
+            (self, Some(right))
+        } else if let Some(piece) = self.pieces.pop() {
+            let (left, right) = piece.split();
+            self.pieces.push(left);
+            let counter = self.counter;
+            let right = right.map(|right| Pieces {
+                pieces: vec![right],
+                counter,
+            });
+            (self, right)
+        } else {
+            (self, None)
+        }
+    }
+
+    fn fold_with<F>(self, folder: F) -> F
+    where
+        F: Folder<Self::Item>,
+    {
+        let mut folder = CountingFolder {
+            base: folder,
+            count: 0,
+        };
+        for piece in self.pieces {
+            folder = piece.fold_with(folder);
+            if folder.full() {
+                break;
+            }
+        }
+        self.counter.fetch_add(folder.count, Ordering::Relaxed);
+        folder.base
+    }
+}
+
+struct CountingFolder<F> {
+    base: F,
+    count: usize,
+}
+
+impl<T, F: Folder<T>> Folder<T> for CountingFolder<F> {
+    type Result = F::Result;
+
+    fn consume(self, item: T) -> Self {
+        CountingFolder {
+            base: self.base.consume(item),
+            count: self.count + 1,
+        }
+    }
+
+    fn complete(self) -> Self::Result {
+        self.base.complete()
+    }
+
+    fn full(&self) -> bool {
+        self.base.full()
+    }
+}
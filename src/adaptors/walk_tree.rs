@@ -1,3 +1,5 @@
+use super::adaptive::{adaptive_consume, AdaptiveWork, BlockPolicy};
+use super::unindexed_blocks::{UnindexedProducerCallback, UnindexedSource};
 use rayon::iter::plumbing::*;
 use rayon::iter::*;
 use std::iter::once;
@@ -107,6 +109,27 @@ where
     }
 }
 
+impl<S, B, I, IT> UnindexedSource for WalkTreePrefix<S, B, I>
+where
+    S: Send,
+    B: Fn(&S) -> I + Send + Sync,
+    IT: DoubleEndedIterator<Item = S>,
+    I: IntoIterator<Item = S, IntoIter = IT> + Send,
+{
+    fn with_unindexed_producer<CB>(self, callback: CB) -> CB::Output
+    where
+        CB: UnindexedProducerCallback<Self::Item>,
+    {
+        let producer = WalkTreePrefixProducer {
+            to_explore: once(self.initial_state).collect(),
+            seen: Vec::new(),
+            breed: &self.breed,
+            phantom: PhantomData,
+        };
+        callback.callback(producer)
+    }
+}
+
 /// Create a tree-like prefix parallel iterator from an initial root node.
 /// The `children_of` function should take a node and return an iterator over its child nodes.
 /// The best parallelization is obtained when the tree is balanced
@@ -346,6 +369,26 @@ where
     }
 }
 
+impl<S, B, I> UnindexedSource for WalkTreePostfix<S, B, I>
+where
+    S: Send,
+    B: Fn(&S) -> I + Send + Sync,
+    I: IntoIterator<Item = S> + Send,
+{
+    fn with_unindexed_producer<CB>(self, callback: CB) -> CB::Output
+    where
+        CB: UnindexedProducerCallback<Self::Item>,
+    {
+        let producer = WalkTreePostfixProducer {
+            to_explore: once(self.initial_state).collect(),
+            seen: Vec::new(),
+            breed: &self.breed,
+            phantom: PhantomData,
+        };
+        callback.callback(producer)
+    }
+}
+
 /// Divide given vector in two equally sized vectors.
 /// Return `None` if initial size is <=1.
 /// We return the first half and keep the last half in `v`.
@@ -543,3 +586,363 @@ where
         self.0.drive_unindexed(consumer)
     }
 }
+
+impl<S, B, I> UnindexedSource for WalkTree<S, B, I>
+where
+    S: Send,
+    B: Fn(&S) -> I + Send + Sync,
+    I: IntoIterator<Item = S> + Send,
+{
+    fn with_unindexed_producer<CB>(self, callback: CB) -> CB::Output
+    where
+        CB: UnindexedProducerCallback<Self::Item>,
+    {
+        self.0.with_unindexed_producer(callback)
+    }
+}
+
+// adaptive
+
+/// Sequential prefix exploration, giving the bottom of its stack to thieves.
+struct AdaptivePrefixWalk<'b, S, B, I> {
+    to_explore: Vec<S>, // nodes to process, next one on top
+    breed: &'b B,
+    block_size: usize,
+    policy: BlockPolicy,
+    phantom: PhantomData<I>,
+}
+
+impl<'b, S, B, I, IT> AdaptiveWork for AdaptivePrefixWalk<'b, S, B, I>
+where
+    S: Send,
+    B: Fn(&S) -> I + Send + Sync,
+    IT: DoubleEndedIterator<Item = S>,
+    I: IntoIterator<Item = S, IntoIter = IT> + Send,
+{
+    type Item = S;
+
+    fn fold_block<F>(&mut self, mut folder: F) -> F
+    where
+        F: Folder<S>,
+    {
+        for _ in 0..self.block_size {
+            let e = match self.to_explore.pop() {
+                Some(e) => e,
+                None => break,
+            };
+            self.to_explore.extend((self.breed)(&e).into_iter().rev());
+            folder = folder.consume(e);
+            if folder.full() {
+                break;
+            }
+        }
+        self.block_size = self.policy.next_size(self.block_size);
+        folder
+    }
+
+    fn divisible(&self) -> bool {
+        self.to_explore.len() > 1
+    }
+
+    fn give_work(&mut self) -> Self {
+        // the bottom of the stack comes last in prefix order
+        let stolen = self.to_explore.drain(..self.to_explore.len() / 2).collect();
+        self.block_size = self.policy.initial_size();
+        AdaptivePrefixWalk {
+            to_explore: stolen,
+            breed: self.breed,
+            block_size: self.block_size,
+            policy: self.policy,
+            phantom: PhantomData,
+        }
+    }
+
+    fn all_is_completed(&self) -> bool {
+        self.to_explore.is_empty()
+    }
+}
+
+/// A node being explored in postfix order.
+struct Frame<S> {
+    parent: Option<S>, // consumed after all children, `None` if given to a thief
+    children: Vec<S>,  // children still to explore, next one on top
+}
+
+/// Sequential postfix exploration, giving the lowest subtrees of its stack to thieves.
+struct AdaptivePostfixWalk<'b, S, B, I> {
+    frames: Vec<Frame<S>>,
+    explored: usize, // number of frames at the bottom without remaining children
+    breed: &'b B,
+    block_size: usize,
+    policy: BlockPolicy,
+    phantom: PhantomData<I>,
+}
+
+impl<'b, S, B, I> AdaptivePostfixWalk<'b, S, B, I> {
+    /// Find the lowest frame with children we can give away,
+    /// and how many of them we give.
+    fn split_point(&self) -> Option<(usize, usize)> {
+        let top = self.frames.len().checked_sub(1)?;
+        self.frames
+            .iter()
+            .enumerate()
+            .skip(self.explored)
+            .map(|(index, frame)| {
+                // below the top frame the worker keeps the frames above,
+                // so it can give away all children
+                let stolen = if index < top {
+                    frame.children.len().div_ceil(2)
+                } else {
+                    frame.children.len() / 2
+                };
+                (index, stolen)
+            })
+            .find(|&(_, stolen)| stolen > 0)
+    }
+}
+
+impl<'b, S, B, I> AdaptiveWork for AdaptivePostfixWalk<'b, S, B, I>
+where
+    S: Send,
+    B: Fn(&S) -> I + Send + Sync,
+    I: IntoIterator<Item = S> + Send,
+{
+    type Item = S;
+
+    fn fold_block<F>(&mut self, mut folder: F) -> F
+    where
+        F: Folder<S>,
+    {
+        let mut consumed = 0;
+        while consumed < self.block_size && !folder.full() {
+            let top = match self.frames.len().checked_sub(1) {
+                Some(top) => top,
+                None => break,
+            };
+            if let Some(node) = self.frames[top].children.pop() {
+                let mut children: Vec<S> = (self.breed)(&node).into_iter().collect();
+                if children.is_empty() {
+                    folder = folder.consume(node);
+                    consumed += 1;
+                } else {
+                    // frames below the top never get new children
+                    if self.explored == top && self.frames[top].children.is_empty() {
+                        self.explored += 1;
+                    }
+                    children.reverse();
+                    self.frames.push(Frame {
+                        parent: Some(node),
+                        children,
+                    });
+                }
+            } else {
+                if let Some(parent) = self.frames.pop().unwrap().parent {
+                    folder = folder.consume(parent);
+                    consumed += 1;
+                }
+                self.explored = self.explored.min(top.saturating_sub(1));
+            }
+        }
+        self.block_size = self.policy.next_size(self.block_size);
+        folder
+    }
+
+    fn divisible(&self) -> bool {
+        self.split_point().is_some()
+    }
+
+    fn give_work(&mut self) -> Self {
+        let (index, stolen) = self.split_point().unwrap();
+        // the thief takes everything coming after the kept children:
+        // the last children of the split frame, its parent, and all frames below.
+        let mut frames: Vec<Frame<S>> = self.frames.drain(..index).collect();
+        let split = &mut self.frames[0];
+        frames.push(Frame {
+            parent: split.parent.take(),
+            children: split.children.drain(..stolen).collect(),
+        });
+        self.explored = 0;
+        self.block_size = self.policy.initial_size();
+        AdaptivePostfixWalk {
+            frames,
+            explored: index,
+            breed: self.breed,
+            block_size: self.block_size,
+            policy: self.policy,
+            phantom: PhantomData,
+        }
+    }
+
+    fn all_is_completed(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+impl<S, B, I> WalkTreePrefix<S, B, I> {
+    /// Walk the tree adaptively: a task explores its part of the tree sequentially,
+    /// processing blocks of nodes following the given [`BlockPolicy`],
+    /// and only gives half of its remaining subtrees to an idle thread when one asks for some.
+    /// This avoids most task creations on deep and narrow trees like backtracking searches.
+    ///
+    /// [`BlockPolicy`]: enum.BlockPolicy.html
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// let v: Vec<u32> = walk_tree_prefix(0u32, |&e| if e < 500 { vec![2 * e + 1, 2 * e + 2] } else { Vec::new() })
+    ///     .adaptive(10)
+    ///     .collect();
+    /// let expected: Vec<u32> = walk_tree_prefix(0u32, |&e| if e < 500 { vec![2 * e + 1, 2 * e + 2] } else { Vec::new() })
+    ///     .collect();
+    /// assert_eq!(v, expected);
+    /// ```
+    pub fn adaptive<P: Into<BlockPolicy>>(self, blocks: P) -> AdaptiveWalkTreePrefix<S, B, I> {
+        let policy = blocks.into();
+        assert!(policy.initial_size() > 0, "blocks cannot be empty");
+        AdaptiveWalkTreePrefix { base: self, policy }
+    }
+}
+
+/// ParallelIterator for adaptive prefix tree walks.
+/// Returned by the [`adaptive()`] method on [`WalkTreePrefix`].
+///
+/// [`adaptive()`]: struct.WalkTreePrefix.html#method.adaptive
+/// [`WalkTreePrefix`]: struct.WalkTreePrefix.html
+#[derive(Debug)]
+pub struct AdaptiveWalkTreePrefix<S, B, I> {
+    base: WalkTreePrefix<S, B, I>,
+    policy: BlockPolicy,
+}
+
+impl<S, B, I, IT> ParallelIterator for AdaptiveWalkTreePrefix<S, B, I>
+where
+    S: Send,
+    B: Fn(&S) -> I + Send + Sync,
+    IT: DoubleEndedIterator<Item = S>,
+    I: IntoIterator<Item = S, IntoIter = IT> + Send,
+{
+    type Item = S;
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        let walk = AdaptivePrefixWalk {
+            to_explore: once(self.base.initial_state).collect(),
+            breed: &self.base.breed,
+            block_size: self.policy.initial_size(),
+            policy: self.policy,
+            phantom: PhantomData,
+        };
+        adaptive_consume(walk, consumer)
+    }
+}
+
+impl<S, B, I> WalkTreePostfix<S, B, I> {
+    /// Walk the tree adaptively: a task explores its part of the tree sequentially,
+    /// processing blocks of nodes following the given [`BlockPolicy`],
+    /// and only gives some of its remaining subtrees to an idle thread when one asks for some.
+    /// This avoids most task creations on deep and narrow trees like backtracking searches.
+    ///
+    /// [`BlockPolicy`]: enum.BlockPolicy.html
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// let v: Vec<u32> = walk_tree_postfix(0u32, |&e| if e < 500 { vec![2 * e + 1, 2 * e + 2] } else { Vec::new() })
+    ///     .adaptive(BlockPolicy::Geometric(4))
+    ///     .collect();
+    /// let expected: Vec<u32> = walk_tree_postfix(0u32, |&e| if e < 500 { vec![2 * e + 1, 2 * e + 2] } else { Vec::new() })
+    ///     .collect();
+    /// assert_eq!(v, expected);
+    /// ```
+    pub fn adaptive<P: Into<BlockPolicy>>(self, blocks: P) -> AdaptiveWalkTreePostfix<S, B, I> {
+        let policy = blocks.into();
+        assert!(policy.initial_size() > 0, "blocks cannot be empty");
+        AdaptiveWalkTreePostfix { base: self, policy }
+    }
+}
+
+/// ParallelIterator for adaptive postfix tree walks.
+/// Returned by the [`adaptive()`] method on [`WalkTreePostfix`].
+///
+/// [`adaptive()`]: struct.WalkTreePostfix.html#method.adaptive
+/// [`WalkTreePostfix`]: struct.WalkTreePostfix.html
+#[derive(Debug)]
+pub struct AdaptiveWalkTreePostfix<S, B, I> {
+    base: WalkTreePostfix<S, B, I>,
+    policy: BlockPolicy,
+}
+
+impl<S, B, I> ParallelIterator for AdaptiveWalkTreePostfix<S, B, I>
+where
+    S: Send,
+    B: Fn(&S) -> I + Send + Sync,
+    I: IntoIterator<Item = S> + Send,
+{
+    type Item = S;
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        let walk = AdaptivePostfixWalk {
+            frames: vec![Frame {
+                parent: None,
+                children: vec![self.base.initial_state],
+            }],
+            explored: 0,
+            breed: &self.base.breed,
+            block_size: self.policy.initial_size(),
+            policy: self.policy,
+            phantom: PhantomData,
+        };
+        adaptive_consume(walk, consumer)
+    }
+}
+
+impl<S, B, I> WalkTree<S, B, I> {
+    /// Walk the tree adaptively, see [`WalkTreePostfix::adaptive()`].
+    ///
+    /// [`WalkTreePostfix::adaptive()`]: struct.WalkTreePostfix.html#method.adaptive
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// // a long chain with a few leaves on the side
+    /// let count = walk_tree(0u32, |&e| if e < 10_000 && e % 2 == 0 { vec![e + 1, e + 2] } else { Vec::new() })
+    ///     .adaptive(100)
+    ///     .count();
+    /// assert_eq!(count, 10_001);
+    /// ```
+    pub fn adaptive<P: Into<BlockPolicy>>(self, blocks: P) -> AdaptiveWalkTree<S, B, I> {
+        AdaptiveWalkTree(self.0.adaptive(blocks))
+    }
+}
+
+/// ParallelIterator for adaptive tree walks.
+/// Returned by the [`adaptive()`] method on [`WalkTree`].
+///
+/// [`adaptive()`]: struct.WalkTree.html#method.adaptive
+/// [`WalkTree`]: struct.WalkTree.html
+#[derive(Debug)]
+pub struct AdaptiveWalkTree<S, B, I>(AdaptiveWalkTreePostfix<S, B, I>);
+
+impl<S, B, I> ParallelIterator for AdaptiveWalkTree<S, B, I>
+where
+    S: Send,
+    B: Fn(&S) -> I + Send + Sync,
+    I: IntoIterator<Item = S> + Send,
+{
+    type Item = S;
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        self.0.drive_unindexed(consumer)
+    }
+}
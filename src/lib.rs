@@ -1,11 +1,36 @@
 mod adaptors;
-// pub(crate) use adaptors::Adaptive;
+pub(crate) use adaptors::Adaptive;
+pub use adaptors::BlockPolicy;
 pub(crate) use adaptors::Logged;
 pub(crate) use adaptors::Scan;
-pub use adaptors::{walk_tree, walk_tree_postfix, walk_tree_prefix};
-pub(crate) use adaptors::{ExponentialBlocks, HomogeneousTuples, SplitMap, Tuples, UniformBlocks};
+pub use adaptors::{
+    walk_tree, walk_tree_postfix, walk_tree_prefix, AdaptiveWalkTree, AdaptiveWalkTreePostfix,
+    AdaptiveWalkTreePrefix, WalkTree, WalkTreePostfix, WalkTreePrefix,
+};
+pub(crate) use adaptors::{
+    BlockSizes, ByBlocks, ExponentialBlocks, ExponentialBlocksRev, ExponentialSizes,
+    HomogeneousTuples, InspectBlocks, SplitMap, TimedBlocks, TimedSizes, Tuples, UniformBlocks,
+    UnindexedBlocks,
+};
+pub use adaptors::{UnindexedProducerCallback, UnindexedSource};
 pub mod prelude;
+#[cfg(feature = "logging")]
+mod scope;
+#[cfg(feature = "logging")]
+pub use scope::{scope, scope_fifo, spawn, Scope, ScopeFifo};
+#[cfg(not(feature = "logging"))]
+mod no_scope;
+#[cfg(not(feature = "logging"))]
+pub use no_scope::{scope, scope_fifo, Scope, ScopeFifo};
+#[cfg(not(feature = "logging"))]
+pub use rayon::{join, join_context, spawn};
+mod trace;
+pub use trace::{
+    chrome_trace, record, simulate, stats, FieldValue, Node, NodeKind, Schedule, ScheduledTask,
+    Stats, TaskGraph,
+};
 
+#[cfg(feature = "logging")]
 pub fn join<A, B, RA, RB>(oper_a: A, oper_b: B) -> (RA, RB)
 where
     A: FnOnce() -> RA + Send,
@@ -30,4 +55,72 @@ where
     rayon::join(logged_oper_a, logged_oper_b)
 }
 
+/// Logged version of `rayon::join_context`.
+///
+/// On top of the spans created by `join`, the "left" and "right" spans record
+/// whether the closure was stolen (`migrated` field) and the index of the
+/// thread executing it (`thread_index` field).
+///
+/// # Example
+///
+/// ```
+/// let (a, b) = diam::join_context(|_| 1, |context| context.migrated());
+/// assert_eq!(a, 1);
+/// ```
+#[cfg(feature = "logging")]
+pub fn join_context<A, B, RA, RB>(oper_a: A, oper_b: B) -> (RA, RB)
+where
+    A: FnOnce(rayon::FnContext) -> RA + Send,
+    B: FnOnce(rayon::FnContext) -> RB + Send,
+    RA: Send,
+    RB: Send,
+{
+    let span = tracing::span!(tracing::Level::TRACE, "parallel");
+    let father_id = span.id();
+    let right_id = father_id.clone();
+    let logged_oper_a = move |context: rayon::FnContext| {
+        let a_span = tracing::span!(
+            parent: father_id,
+            tracing::Level::TRACE,
+            "left",
+            migrated = context.migrated(),
+            thread_index = tracing::field::Empty
+        );
+        if let Some(index) = rayon::current_thread_index() {
+            a_span.record("thread_index", index);
+        }
+        let _guard = a_span.enter();
+        oper_a(context)
+    };
+    let logged_oper_b = move |context: rayon::FnContext| {
+        let b_span = tracing::span!(
+            parent: right_id,
+            tracing::Level::TRACE,
+            "right",
+            migrated = context.migrated(),
+            thread_index = tracing::field::Empty
+        );
+        if let Some(index) = rayon::current_thread_index() {
+            b_span.record("thread_index", index);
+        }
+        let _guard = b_span.enter();
+        oper_b(context)
+    };
+    let _guard = span.enter();
+    rayon::join_context(logged_oper_a, logged_oper_b)
+}
+
+#[cfg(feature = "logging")]
 pub use fast_tracer::{display_svg, svg};
+
+/// Run `op`. Since the `logging` feature is disabled no svg file is generated.
+#[cfg(not(feature = "logging"))]
+pub fn svg<P: AsRef<std::path::Path>, R, F: FnOnce() -> R>(_path: P, op: F) -> std::io::Result<R> {
+    Ok(op())
+}
+
+/// Run `op`. Since the `logging` feature is disabled nothing is displayed.
+#[cfg(not(feature = "logging"))]
+pub fn display_svg<R, F: FnOnce() -> R>(op: F) -> std::io::Result<R> {
+    Ok(op())
+}
@@ -0,0 +1,53 @@
+//! Pass-through replacement for logged scopes when the `logging` feature is disabled.
+
+/// Same as `rayon::scope`. Since the `logging` feature is disabled nothing is logged.
+pub fn scope<'scope, OP, R>(op: OP) -> R
+where
+    OP: FnOnce(&Scope<'_, 'scope>) -> R + Send,
+    R: Send,
+{
+    rayon::scope(|s| op(&Scope { base: s }))
+}
+
+/// Wrapper around `rayon::Scope`, obtained with [`scope()`].
+///
+/// [`scope()`]: fn.scope.html
+pub struct Scope<'a, 'scope> {
+    base: &'a rayon::Scope<'scope>,
+}
+
+impl<'a, 'scope> Scope<'a, 'scope> {
+    /// Spawn a job into the scope, see `rayon::Scope::spawn`.
+    pub fn spawn<BODY>(&self, body: BODY)
+    where
+        BODY: FnOnce(&Scope<'_, 'scope>) + Send + 'scope,
+    {
+        self.base.spawn(move |s| body(&Scope { base: s }))
+    }
+}
+
+/// Same as `rayon::scope_fifo`. Since the `logging` feature is disabled nothing is logged.
+pub fn scope_fifo<'scope, OP, R>(op: OP) -> R
+where
+    OP: FnOnce(&ScopeFifo<'_, 'scope>) -> R + Send,
+    R: Send,
+{
+    rayon::scope_fifo(|s| op(&ScopeFifo { base: s }))
+}
+
+/// Wrapper around `rayon::ScopeFifo`, obtained with [`scope_fifo()`].
+///
+/// [`scope_fifo()`]: fn.scope_fifo.html
+pub struct ScopeFifo<'a, 'scope> {
+    base: &'a rayon::ScopeFifo<'scope>,
+}
+
+impl<'a, 'scope> ScopeFifo<'a, 'scope> {
+    /// Spawn a job into the scope, see `rayon::ScopeFifo::spawn_fifo`.
+    pub fn spawn_fifo<BODY>(&self, body: BODY)
+    where
+        BODY: FnOnce(&ScopeFifo<'_, 'scope>) + Send + 'scope,
+    {
+        self.base.spawn_fifo(move |s| body(&ScopeFifo { base: s }))
+    }
+}
@@ -1,7 +1,15 @@
+pub use crate::svg;
+pub use crate::BlockPolicy;
 pub use crate::{walk_tree, walk_tree_postfix, walk_tree_prefix};
-use crate::{ExponentialBlocks, HomogeneousTuples, Logged, Scan, SplitMap, Tuples, UniformBlocks};
-pub use fast_tracer::svg;
+use crate::{
+    Adaptive, BlockSizes, ByBlocks, ExponentialBlocks, ExponentialBlocksRev, ExponentialSizes,
+    HomogeneousTuples, InspectBlocks, Logged, Scan, SplitMap, TimedBlocks, TimedSizes, Tuples,
+    UniformBlocks, UnindexedBlocks,
+};
+pub use crate::{UnindexedProducerCallback, UnindexedSource};
 use rayon::prelude::*;
+use std::borrow::Cow;
+use std::time::Duration;
 
 pub trait DParallelIterator: ParallelIterator {
     /// Log each task with the `tracing` crate.
@@ -17,7 +25,37 @@ pub trait DParallelIterator: ParallelIterator {
     /// use diam::prelude::*;
     /// svg("collect.svg",||(0..1000).into_par_iter().log("collect log").collect::<Vec<_>>());
     /// ```
-    fn log(self, tag: &'static str) -> Logged<Self> {
+    ///
+    /// Tags can be computed at runtime.
+    ///
+    /// ```no_run
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// svg("loop.svg", || {
+    ///     for i in 0..3 {
+    ///         (0..1000).into_par_iter().log(format!("sum {}", i)).sum::<u32>();
+    ///     }
+    /// });
+    /// ```
+    ///
+    /// Logging an indexed iterator keeps it indexed.
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// let v: Vec<(u32, u32)> = (0..100u32).into_par_iter().log("zip").zip(0..100).collect();
+    /// assert!(v.into_iter().all(|(a, b)| a == b));
+    /// ```
+    ///
+    /// Long chains of sequential divisions are fine.
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// let count = (0..1_000_000u32).into_par_iter().log("blocks").by_uniform_blocks(1).count();
+    /// assert_eq!(count, 1_000_000);
+    /// ```
+    fn log(self, tag: impl Into<Cow<'static, str>>) -> Logged<Self> {
         Logged::new(self, tag)
     }
 
@@ -134,8 +172,137 @@ pub trait DIndexedParallelIterator: IndexedParallelIterator {
     /// Each subrange is treated in parallel, while all subranges are treated sequentially.
     /// We therefore ensure a logarithmic number of blocks (and overhead) while guaranteeing
     /// we stop at the first block containing the searched data.
+    ///
+    /// Blocks are lost if positional adaptors or searches (`zip`, `enumerate`,
+    /// `position_first`...) come **after** this adaptor,
+    /// see [`by_uniform_blocks()`].
+    ///
+    /// [`by_uniform_blocks()`]: #method.by_uniform_blocks
     fn by_exponential_blocks(self) -> ExponentialBlocks<Self> {
-        ExponentialBlocks::new(self)
+        ByBlocks::new(
+            self,
+            ExponentialSizes::new(rayon::current_num_threads(), 2.0),
+        )
+    }
+
+    /// Process the iterator adaptively: each task processes its items sequentially,
+    /// by blocks, and only divides its remaining work
+    /// when an idle thread asks for some.
+    /// Block sizes follow the given [`BlockPolicy`], a plain size meaning fixed size blocks.
+    /// For irregular workloads this creates far fewer tasks than rayon's eager division.
+    ///
+    /// All items processed by a task are folded into the same folder:
+    /// results are only reduced when some work was stolen.
+    /// This makes expensive reductions (like merging vectors or hash maps)
+    /// cheap when threads are not idle.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// assert_eq!(
+    ///     (0..100_000u32).into_par_iter().adaptive(100).map(|e| e as u64).sum::<u64>(),
+    ///     (0..100_000u64).sum()
+    /// );
+    /// let v: Vec<u32> = (0..10_000).into_par_iter().adaptive(10).filter(|e| e % 3 == 0).collect();
+    /// assert_eq!(v, (0..10_000).filter(|e| e % 3 == 0).collect::<Vec<_>>());
+    /// ```
+    ///
+    /// Adaptive iterators can be nested and used in any thread pool.
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// let pool = rayon::ThreadPoolBuilder::new().num_threads(3).build().unwrap();
+    /// let s: u32 = pool.install(|| {
+    ///     (0..100u32)
+    ///         .into_par_iter()
+    ///         .adaptive(2)
+    ///         .map(|i| (0..i).into_par_iter().adaptive(5).sum::<u32>())
+    ///         .sum()
+    /// });
+    /// assert_eq!(s, (0..100u32).map(|i| (0..i).sum::<u32>()).sum());
+    /// ```
+    ///
+    /// Blocks can also grow while nobody steals.
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// assert_eq!(
+    ///     (0..100_000u32).into_par_iter().adaptive(BlockPolicy::Geometric(10)).max(),
+    ///     Some(99_999)
+    /// );
+    /// ```
+    fn adaptive<B: Into<BlockPolicy>>(self, blocks: B) -> Adaptive<Self> {
+        Adaptive::new(self, blocks.into())
+    }
+
+    /// Same as [`by_exponential_blocks()`] except that the first block contains
+    /// `first` items and each block is `factor` times larger than the previous one.
+    /// `factor` does not need to be an integer but it cannot be lower than 1.
+    /// A `first` size of 0 is taken as 1.
+    /// Blocks sizes can also be capped with `max_block_size`.
+    /// As with all blocking adaptors, positional adaptors must come before it.
+    ///
+    /// [`by_exponential_blocks()`]: #method.by_exponential_blocks
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// assert_eq!((0..10_000).into_par_iter()
+    ///                       .by_exponential_blocks_with(100, 1.5)
+    ///                       .max_block_size(2_000)
+    ///                       .find_first(|&e| e==4_999), Some(4_999))
+    /// ```
+    fn by_exponential_blocks_with(self, first: usize, factor: f64) -> ExponentialBlocks<Self> {
+        ByBlocks::new(self, ExponentialSizes::new(first, factor))
+    }
+
+    /// Mirror image of [`by_exponential_blocks()`]: blocks of increasing sizes
+    /// are taken from the **end** of the iterator.
+    /// Items still keep their order and blocks can be capped with `max_block_size`.
+    ///
+    /// This helps with searches near the tail, like [`find_last()`].
+    /// For positions, `enumerate` before blocking and search for the last pair:
+    /// rayon's [`position_last()`] enumerates **after** blocking, which loses the blocks.
+    ///
+    /// [`by_exponential_blocks()`]: #method.by_exponential_blocks
+    /// [`find_last()`]: trait.ParallelIterator.html#method.find_last
+    /// [`position_last()`]: trait.IndexedParallelIterator.html#method.position_last
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// let v: Vec<u32> = (0..1_000_000).collect();
+    /// let evaluated = AtomicUsize::new(0);
+    /// assert_eq!(v.par_iter()
+    ///             .by_exponential_blocks_rev()
+    ///             .find_last(|&&e| {
+    ///                 evaluated.fetch_add(1, Ordering::Relaxed);
+    ///                 e % 1_000 == 0
+    ///             }), Some(&999_000));
+    /// assert!(evaluated.load(Ordering::Relaxed) < 100_000);
+    ///
+    /// let evaluated = AtomicUsize::new(0);
+    /// assert_eq!(v.par_iter()
+    ///             .enumerate()
+    ///             .by_exponential_blocks_rev()
+    ///             .find_last(|&(_, &e)| {
+    ///                 evaluated.fetch_add(1, Ordering::Relaxed);
+    ///                 e == 999_990
+    ///             })
+    ///             .map(|(i, _)| i), Some(999_990));
+    /// assert!(evaluated.load(Ordering::Relaxed) < 100_000);
+    /// ```
+    fn by_exponential_blocks_rev(self) -> ExponentialBlocksRev<Self> {
+        ExponentialBlocksRev::new(self)
     }
 
     /// Normally, parallel iterators are recursively divided into tasks in parallel.
@@ -155,9 +322,201 @@ pub trait DIndexedParallelIterator: IndexedParallelIterator {
     ///     .reduce(Vec::new, |mut v1, mut v2| { v1.append(&mut v2); v1});
     /// assert_eq!(v, (0u32..10_000_000).collect::<Vec<u32>>());
     /// ```
+    ///
+    /// Blocking keeps the iterator indexed, so it can be collected into a vector.
+    /// However positional adaptors like `zip` or `enumerate`, and searches built on them
+    /// like `position_first` or `position_last`, request the
+    /// iterator's producer and rayon then divides it as usual:
+    /// **blocks are lost when positional adaptors come after blocking**.
+    /// This holds for all blocking adaptors. Apply them before blocking instead.
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// let mut v = Vec::new();
+    /// (0u32..10_000).into_par_iter().by_uniform_blocks(1_000).collect_into_vec(&mut v);
+    /// assert!(v.into_par_iter().enumerate().by_exponential_blocks().all(|(i, e)| i as u32 == e));
+    /// ```
     fn by_uniform_blocks(self, blocks_size: usize) -> UniformBlocks<Self> {
-        UniformBlocks::new(self, blocks_size)
+        ByBlocks::new(self, std::iter::repeat(blocks_size))
+    }
+
+    /// Normally, parallel iterators are recursively divided into tasks in parallel.
+    /// This adaptor changes the default behavior by splitting the iterator into a **sequence**
+    /// of parallel iterators whose sizes are given by the `sizes` iterator.
+    /// Once `sizes` is exhausted, all remaining items form the last block.
+    /// Sizes of 0 are taken as 1.
+    ///
+    /// This is the general version of [`by_exponential_blocks()`] and [`by_uniform_blocks()`]
+    /// which allows to experiment with any schedule.
+    /// Like them, it loses its blocks under positional adaptors (`zip`, `enumerate`,
+    /// `position_first`...) so these must be applied first.
+    ///
+    /// [`by_exponential_blocks()`]: #method.by_exponential_blocks
+    /// [`by_uniform_blocks()`]: #method.by_uniform_blocks
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// // blocks sizes follow the fibonacci sequence
+    /// let fibonacci = std::iter::successors(Some((1, 1)), |&(a, b)| Some((b, a + b))).map(|(a, _)| a);
+    /// assert_eq!((0..10_000).into_par_iter()
+    ///                       .by_blocks(fibonacci)
+    ///                       .find_first(|&e| e==4_999), Some(4_999))
+    /// ```
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// assert_eq!((0..100).into_par_iter().by_blocks(std::iter::repeat(0)).count(), 100);
+    /// ```
+    fn by_blocks<S>(self, sizes: S) -> ByBlocks<Self, S::IntoIter>
+    where
+        S: IntoIterator<Item = usize>,
+        S::IntoIter: BlockSizes,
+    {
+        ByBlocks::new(self, sizes.into_iter())
+    }
+
+    /// Same as [`by_blocks()`] except that `inspect` is called after each block
+    /// with the block's index, the index of its first item and its size.
+    /// This allows to report progress or save checkpoints between blocks.
+    /// If `inspect` returns `false` no more blocks are processed.
+    ///
+    /// [`by_blocks()`]: #method.by_blocks
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// let mut done = 0;
+    /// let s: u32 = (0..10_000u32)
+    ///     .into_par_iter()
+    ///     .by_blocks_inspect(std::iter::repeat(1_000), |index, start, len| {
+    ///         assert_eq!(start, index * 1_000);
+    ///         done += len;
+    ///         index < 2 // stop after the third block
+    ///     })
+    ///     .sum();
+    /// assert_eq!(done, 3_000);
+    /// assert_eq!(s, (0..3_000).sum());
+    /// ```
+    ///
+    /// Since blocks can stop early the resulting iterator is not indexed,
+    /// but it can still be collected.
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// let v: Vec<u32> = (0..10_000u32)
+    ///     .into_par_iter()
+    ///     .by_blocks_inspect(std::iter::repeat(1_000), |index, _, _| index < 2)
+    ///     .collect();
+    /// assert_eq!(v, (0..3_000).collect::<Vec<_>>());
+    /// ```
+    fn by_blocks_inspect<S, F>(self, sizes: S, inspect: F) -> InspectBlocks<Self, S::IntoIter, F>
+    where
+        S: IntoIterator<Item = usize>,
+        S::IntoIter: BlockSizes,
+        F: FnMut(usize, usize, usize) -> bool + Send,
+    {
+        InspectBlocks::new(self, sizes.into_iter(), inspect)
+    }
+
+    /// Normally, parallel iterators are recursively divided into tasks in parallel.
+    /// This adaptor changes the default behavior by splitting the iterator into a **sequence**
+    /// of parallel iterators, each one taking about `target` time.
+    /// The time taken by each block is measured and the size of the next one
+    /// is adjusted accordingly.
+    /// This gives a predictable latency for interruptible computations even
+    /// when the cost of items varies a lot across the input.
+    ///
+    /// Blocks are lost if positional adaptors or searches (`zip`, `enumerate`,
+    /// `position_first`...) come **after** this adaptor,
+    /// see [`by_uniform_blocks()`].
+    ///
+    /// [`by_uniform_blocks()`]: #method.by_uniform_blocks
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// use std::time::Duration;
+    /// assert_eq!((0..100_000).into_par_iter()
+    ///                        .by_timed_blocks(Duration::from_millis(1))
+    ///                        .find_first(|&e| e==4_999), Some(4_999))
+    /// ```
+    fn by_timed_blocks(self, target: Duration) -> TimedBlocks<Self> {
+        ByBlocks::new(self, TimedSizes::new(target))
     }
 }
 
 impl<I: IndexedParallelIterator> DIndexedParallelIterator for I {}
+
+pub trait DUnindexedParallelIterator: UnindexedSource {
+    /// Process the iterator as a **sequence** of parallel blocks of about `block_size` items,
+    /// like [`by_uniform_blocks()`] does for indexed iterators.
+    ///
+    /// Since the number of items is unknown, blocks are obtained by repeatedly
+    /// dividing the leftmost remaining part of the iterator and sizes are estimated
+    /// from the numbers of items in previous blocks.
+    /// Interruptible computations like [`find_first()`] stop after the first block
+    /// containing the searched data.
+    ///
+    /// This is only available for iterators giving access to their producer
+    /// through [`UnindexedSource`]: currently the tree walks ([`walk_tree()`],
+    /// [`walk_tree_prefix()`] and [`walk_tree_postfix()`]).
+    /// Rayon's own unindexed iterators like `par_bridge`, `split`, `filter` or `flat_map`
+    /// keep their producers private and cannot be processed by blocks.
+    /// When they come from an indexed iterator, block it first instead,
+    /// for example with `.by_uniform_blocks(n).filter(..)`.
+    ///
+    /// [`by_uniform_blocks()`]: trait.DIndexedParallelIterator.html#method.by_uniform_blocks
+    /// [`find_first()`]: trait.ParallelIterator.html#method.find_first
+    /// [`UnindexedSource`]: trait.UnindexedSource.html
+    /// [`walk_tree()`]: fn.walk_tree.html
+    /// [`walk_tree_prefix()`]: fn.walk_tree_prefix.html
+    /// [`walk_tree_postfix()`]: fn.walk_tree_postfix.html
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// let children = |&e: &u32| if e < 1 << 16 { vec![2 * e, 2 * e + 1] } else { Vec::new() };
+    /// assert_eq!(
+    ///     walk_tree_prefix(1, children)
+    ///         .by_unindexed_blocks(1_000)
+    ///         .find_first(|&e| e % 1_000 == 999),
+    ///     walk_tree_prefix(1, children).find_first(|&e| e % 1_000 == 999)
+    /// );
+    /// assert_eq!(
+    ///     walk_tree_postfix(1, children).by_unindexed_blocks(100).count(),
+    ///     (1 << 17) - 1
+    /// );
+    /// ```
+    ///
+    /// Filtered indexed iterators are blocked before filtering.
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// assert_eq!(
+    ///     (0..100_000u32)
+    ///         .into_par_iter()
+    ///         .by_uniform_blocks(1_000)
+    ///         .filter(|e| e % 3 == 0)
+    ///         .find_first(|&e| e > 50_000),
+    ///     Some(50_001)
+    /// );
+    /// ```
+    fn by_unindexed_blocks(self, block_size: usize) -> UnindexedBlocks<Self> {
+        UnindexedBlocks::new(self, block_size)
+    }
+}
+
+impl<I: UnindexedSource> DUnindexedParallelIterator for I {}
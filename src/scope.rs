@@ -0,0 +1,160 @@
+//! Logged counterparts to rayon's `scope`, `scope_fifo` and `spawn`.
+use tracing::span::Id;
+
+/// Logged version of `rayon::scope`.
+///
+/// The scope is logged as a "parallel" task, the body `op` as its "left" sub-task
+/// and each spawned job as a "right" sub-task.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// let counter = AtomicUsize::new(0);
+/// diam::scope(|s| {
+///     for _ in 0..10 {
+///         s.spawn(|s| {
+///             counter.fetch_add(1, Ordering::Relaxed);
+///             s.spawn(|_| {
+///                 counter.fetch_add(1, Ordering::Relaxed);
+///             });
+///         });
+///     }
+/// });
+/// assert_eq!(counter.into_inner(), 20);
+/// ```
+///
+/// Jobs spawned by a job are its sub-tasks.
+///
+/// ```
+/// use diam::NodeKind;
+/// let (_, graph) = diam::record(|| diam::scope(|s| s.spawn(|s| s.spawn(|_| ()))));
+/// let nodes = graph.nodes();
+/// let spawner = |n: &diam::Node| n.parent.and_then(|p| nodes[p].parent).map(|g| nodes[g].kind);
+/// assert_eq!(
+///     nodes.iter().filter(|n| n.kind == NodeKind::Right && spawner(n) == Some(NodeKind::Right)).count(),
+///     1
+/// );
+/// ```
+pub fn scope<'scope, OP, R>(op: OP) -> R
+where
+    OP: FnOnce(&Scope<'_, 'scope>) -> R + Send,
+    R: Send,
+{
+    let span = tracing::span!(tracing::Level::TRACE, "parallel");
+    let father_id = span.id();
+    let _guard = span.enter();
+    rayon::scope(|s| {
+        let left_span = tracing::span!(parent: father_id.clone(), tracing::Level::TRACE, "left");
+        let _guard = left_span.enter();
+        op(&Scope { base: s, father_id })
+    })
+}
+
+/// Run a spawned job as the "right" sub-task of `father_id`.
+///
+/// Like the scope's body, the job is itself logged as a "parallel" task
+/// with `op` as its "left" sub-task: `op` receives the id of the "parallel" span
+/// so that jobs it spawns are parented under this job.
+fn run_job<R>(father_id: Option<Id>, op: impl FnOnce(Option<Id>) -> R) -> R {
+    let span = tracing::span!(parent: father_id, tracing::Level::TRACE, "right");
+    let _guard = span.enter();
+    let parallel_span = tracing::span!(parent: span.id(), tracing::Level::TRACE, "parallel");
+    let job_id = parallel_span.id();
+    let _parallel_guard = parallel_span.enter();
+    let left_span = tracing::span!(parent: job_id.clone(), tracing::Level::TRACE, "left");
+    let _left_guard = left_span.enter();
+    op(job_id)
+}
+
+/// Logged version of `rayon::Scope`, obtained with [`scope()`].
+///
+/// [`scope()`]: fn.scope.html
+pub struct Scope<'a, 'scope> {
+    base: &'a rayon::Scope<'scope>,
+    father_id: Option<Id>,
+}
+
+impl<'a, 'scope> Scope<'a, 'scope> {
+    /// Spawn a logged job into the scope, see `rayon::Scope::spawn`.
+    pub fn spawn<BODY>(&self, body: BODY)
+    where
+        BODY: FnOnce(&Scope<'_, 'scope>) + Send + 'scope,
+    {
+        let father_id = self.father_id.clone();
+        self.base
+            .spawn(move |s| run_job(father_id, |father_id| body(&Scope { base: s, father_id })))
+    }
+}
+
+/// Logged version of `rayon::scope_fifo`.
+///
+/// The scope is logged as a "parallel" task, the body `op` as its "left" sub-task
+/// and each spawned job as a "right" sub-task.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Mutex;
+/// let v = Mutex::new(Vec::new());
+/// diam::scope_fifo(|s| {
+///     for i in 0..10 {
+///         let v = &v;
+///         s.spawn_fifo(move |_| v.lock().unwrap().push(i));
+///     }
+/// });
+/// assert_eq!(v.into_inner().unwrap().len(), 10);
+/// ```
+pub fn scope_fifo<'scope, OP, R>(op: OP) -> R
+where
+    OP: FnOnce(&ScopeFifo<'_, 'scope>) -> R + Send,
+    R: Send,
+{
+    let span = tracing::span!(tracing::Level::TRACE, "parallel");
+    let father_id = span.id();
+    let _guard = span.enter();
+    rayon::scope_fifo(|s| {
+        let left_span = tracing::span!(parent: father_id.clone(), tracing::Level::TRACE, "left");
+        let _guard = left_span.enter();
+        op(&ScopeFifo { base: s, father_id })
+    })
+}
+
+/// Logged version of `rayon::ScopeFifo`, obtained with [`scope_fifo()`].
+///
+/// [`scope_fifo()`]: fn.scope_fifo.html
+pub struct ScopeFifo<'a, 'scope> {
+    base: &'a rayon::ScopeFifo<'scope>,
+    father_id: Option<Id>,
+}
+
+impl<'a, 'scope> ScopeFifo<'a, 'scope> {
+    /// Spawn a logged job into the scope, see `rayon::ScopeFifo::spawn_fifo`.
+    pub fn spawn_fifo<BODY>(&self, body: BODY)
+    where
+        BODY: FnOnce(&ScopeFifo<'_, 'scope>) + Send + 'scope,
+    {
+        let father_id = self.father_id.clone();
+        self.base.spawn_fifo(move |s| {
+            run_job(father_id, |father_id| {
+                body(&ScopeFifo { base: s, father_id })
+            })
+        })
+    }
+}
+
+/// Logged version of `rayon::spawn`.
+///
+/// The job is logged as the "right" sub-task of a "parallel" task
+/// created by the caller.
+pub fn spawn<F>(func: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    let span = tracing::span!(tracing::Level::TRACE, "parallel");
+    rayon::spawn(move || {
+        let right_span = tracing::span!(parent: span.id(), tracing::Level::TRACE, "right");
+        let _guard = right_span.enter();
+        func()
+    })
+}
@@ -0,0 +1,92 @@
+//! Chrome trace-event export.
+use super::{record_spans, FieldValue, RawSpan};
+use std::io::Write;
+use std::path::Path;
+
+/// Escape a string for inclusion in a json file.
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+impl FieldValue {
+    fn to_json(&self) -> String {
+        match self {
+            FieldValue::U64(u) => u.to_string(),
+            FieldValue::I64(i) => i.to_string(),
+            FieldValue::Bool(b) => b.to_string(),
+            FieldValue::Str(s) => json_string(s),
+        }
+    }
+}
+
+/// Write all spans as chrome trace-events ("complete" events, timestamps in microseconds).
+fn write_chrome_trace<W: Write>(mut output: W, spans: &[RawSpan]) -> std::io::Result<()> {
+    writeln!(output, "{{\"traceEvents\":[")?;
+    for (index, span) in spans.iter().enumerate() {
+        let end = span.end.unwrap_or(span.start);
+        // highlight stolen tasks
+        let migrated = span.fields.contains(&("migrated", FieldValue::Bool(true)));
+        write!(
+            output,
+            "{{\"name\":{},\"cat\":\"diam\",\"ph\":\"X\",{}\"pid\":0,\"tid\":{},\"ts\":{:.3},\"dur\":{:.3},\"args\":{{\"id\":{}",
+            json_string(span.name),
+            if migrated { "\"cname\":\"terrible\"," } else { "" },
+            span.thread,
+            span.start as f64 / 1_000.0,
+            (end - span.start) as f64 / 1_000.0,
+            index + 1
+        )?;
+        if let Some(parent) = span.parent {
+            write!(output, ",\"parent\":{}", parent)?;
+        }
+        for (name, value) in &span.fields {
+            write!(output, ",{}:{}", json_string(name), value.to_json())?;
+        }
+        let separator = if index + 1 == spans.len() { "" } else { "," };
+        writeln!(output, "}}}}{}", separator)?;
+    }
+    writeln!(output, "]}}")
+}
+
+/// Run `op`, recording all logged tasks, and save them in `path`
+/// as a chrome trace-event json file.
+/// The file can then be opened with perfetto or chrome://tracing.
+/// Tasks stolen in [`join_context()`] are highlighted.
+///
+/// [`join_context()`]: fn.join_context.html
+///
+/// `op` is run inside a dedicated thread pool with as many threads as the current one.
+///
+/// # Example
+///
+/// ```no_run
+/// use rayon::prelude::*;
+/// use diam::prelude::*;
+/// diam::chrome_trace("sum.json", || (0..1000).into_par_iter().log("sum").sum::<u32>())
+///     .expect("failed saving trace");
+/// ```
+pub fn chrome_trace<P, R, F>(path: P, op: F) -> std::io::Result<R>
+where
+    P: AsRef<Path>,
+    F: FnOnce() -> R + Send,
+    R: Send,
+{
+    let (r, spans, _) = record_spans(op);
+    let file = std::fs::File::create(path)?;
+    write_chrome_trace(std::io::BufWriter::new(file), &spans)?;
+    Ok(r)
+}
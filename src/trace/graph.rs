@@ -0,0 +1,157 @@
+//! In-memory fork-join graph of a recorded computation.
+use super::{FieldValue, RawSpan};
+use std::time::Duration;
+
+/// Kind of a recorded task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    /// A task divided into two sub-tasks ("parallel" span).
+    Parallel,
+    /// Left sub-task of a divided task ("left" span).
+    Left,
+    /// Right sub-task of a divided task, or a root task ("right" span).
+    Right,
+    /// Sequential processing of items ("fold" span).
+    Fold,
+    /// Merge of the results of two sub-tasks ("reduce" span).
+    Reduce,
+}
+
+impl NodeKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "parallel" => Some(NodeKind::Parallel),
+            "left" => Some(NodeKind::Left),
+            "right" => Some(NodeKind::Right),
+            "fold" => Some(NodeKind::Fold),
+            "reduce" => Some(NodeKind::Reduce),
+            _ => None,
+        }
+    }
+}
+
+/// A task in a [`TaskGraph`].
+///
+/// [`TaskGraph`]: struct.TaskGraph.html
+#[derive(Debug, Clone)]
+pub struct Node {
+    /// What this task does.
+    pub kind: NodeKind,
+    /// Index of the enclosing task.
+    pub parent: Option<usize>,
+    /// Indices of all sub-tasks, in creation order.
+    pub children: Vec<usize>,
+    /// Index of the thread which started the task.
+    pub thread: usize,
+    /// Start time, measured from the beginning of the recording.
+    pub start: Duration,
+    /// End time, measured from the beginning of the recording.
+    pub end: Duration,
+    /// Tag of the logged iterator, if any.
+    pub label: Option<String>,
+    /// All other fields recorded on the span.
+    pub fields: Vec<(&'static str, FieldValue)>,
+}
+
+impl Node {
+    /// Time elapsed between the start and the end of the task.
+    pub fn duration(&self) -> Duration {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// Return the value of the field of given name, if recorded.
+    pub fn field(&self, name: &str) -> Option<&FieldValue> {
+        self.fields
+            .iter()
+            .find(|(field_name, _)| *field_name == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Fork-join graph of a computation, obtained with the [`record()`] function.
+///
+/// Nodes are stored in creation order, which means a task is always stored
+/// before all its sub-tasks.
+///
+/// [`record()`]: fn.record.html
+#[derive(Debug, Clone, Default)]
+pub struct TaskGraph {
+    nodes: Vec<Node>,
+    roots: Vec<usize>,
+    threads: usize,
+}
+
+impl TaskGraph {
+    /// Build the graph from all recorded spans, keeping only the tasks.
+    pub(super) fn new(spans: &[RawSpan], threads: usize) -> Self {
+        let mut graph = TaskGraph::default();
+        // for each span, the index of the closest task among itself and its ancestors
+        let mut tasks: Vec<Option<usize>> = Vec::with_capacity(spans.len());
+        for span in spans {
+            let parent = span
+                .parent
+                .and_then(|id| tasks.get(id as usize - 1).copied().flatten());
+            let task = NodeKind::from_name(span.name).map(|kind| {
+                let index = graph.nodes.len();
+                let (labels, fields) = span
+                    .fields
+                    .iter()
+                    .cloned()
+                    .partition::<Vec<_>, _>(|(name, _)| *name == "label");
+                let label = labels.into_iter().next().map(|(_, value)| match value {
+                    FieldValue::Str(s) => s,
+                    other => format!("{:?}", other),
+                });
+                graph.nodes.push(Node {
+                    kind,
+                    parent,
+                    children: Vec::new(),
+                    thread: span.thread,
+                    start: Duration::from_nanos(span.start),
+                    end: Duration::from_nanos(span.end.unwrap_or(span.start)),
+                    label,
+                    fields,
+                });
+                match parent {
+                    Some(parent) => graph.nodes[parent].children.push(index),
+                    None => graph.roots.push(index),
+                }
+                index
+            });
+            tasks.push(task.or(parent));
+        }
+        graph.threads = graph
+            .nodes
+            .iter()
+            .map(|n| n.thread + 1)
+            .max()
+            .unwrap_or(0)
+            .max(threads);
+        graph
+    }
+
+    /// All tasks.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Indices of all tasks without parents.
+    pub fn roots(&self) -> &[usize] {
+        &self.roots
+    }
+
+    /// Number of threads available during the recording.
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Time elapsed between the start of the first task and the end of the last one.
+    pub fn duration(&self) -> Duration {
+        let start = self.nodes.iter().map(|n| n.start).min();
+        let end = self.nodes.iter().map(|n| n.end).max();
+        match (start, end) {
+            (Some(start), Some(end)) => end.saturating_sub(start),
+            _ => Duration::default(),
+        }
+    }
+}
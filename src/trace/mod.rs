@@ -0,0 +1,88 @@
+//! Record the spans of logged computations and export them.
+//!
+//! When the `logging` feature is disabled nothing is recorded:
+//! all functions simply run their operation.
+#[cfg(feature = "logging")]
+mod chrome;
+#[cfg(feature = "logging")]
+pub use chrome::chrome_trace;
+#[cfg(feature = "logging")]
+mod recorder;
+#[cfg(feature = "logging")]
+use recorder::record_spans;
+mod graph;
+pub use graph::{Node, NodeKind, TaskGraph};
+mod stats;
+pub use stats::{stats, Stats};
+mod simulate;
+pub use simulate::{simulate, Schedule, ScheduledTask};
+
+/// Value of a field attached to a span.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    U64(u64),
+    I64(i64),
+    Bool(bool),
+    Str(String),
+}
+
+/// A span as seen by the recorder.
+#[derive(Debug, Clone)]
+struct RawSpan {
+    name: &'static str,
+    parent: Option<u64>,
+    thread: usize,
+    start: u64,
+    end: Option<u64>,
+    fields: Vec<(&'static str, FieldValue)>,
+}
+
+/// Run `op`. Since the `logging` feature is disabled no span is recorded.
+#[cfg(not(feature = "logging"))]
+fn record_spans<R, F>(op: F) -> (R, Vec<RawSpan>, usize)
+where
+    F: FnOnce() -> R + Send,
+    R: Send,
+{
+    (op(), Vec::new(), rayon::current_num_threads())
+}
+
+/// Run `op`. Since the `logging` feature is disabled no trace file is generated.
+#[cfg(not(feature = "logging"))]
+pub fn chrome_trace<P, R, F>(_path: P, op: F) -> std::io::Result<R>
+where
+    P: AsRef<std::path::Path>,
+    F: FnOnce() -> R + Send,
+    R: Send,
+{
+    Ok(op())
+}
+
+/// Run `op`, recording all logged tasks, and return its result
+/// together with the recorded fork-join graph.
+///
+/// `op` is run inside a dedicated thread pool with as many threads as the current one.
+/// When the `logging` feature is disabled `op` is just run and the graph is empty.
+///
+/// # Example
+///
+/// ```
+/// use rayon::prelude::*;
+/// use diam::prelude::*;
+/// use diam::NodeKind;
+/// let (s, graph) = diam::record(|| (0..1_000u32).into_par_iter().log("sum").sum::<u32>());
+/// assert_eq!(s, 499_500);
+/// #[cfg(feature = "logging")]
+/// assert!(graph
+///     .nodes()
+///     .iter()
+///     .any(|n| n.kind == NodeKind::Fold && n.label.as_deref() == Some("sum")));
+/// ```
+pub fn record<R, F>(op: F) -> (R, TaskGraph)
+where
+    F: FnOnce() -> R + Send,
+    R: Send,
+{
+    let (r, spans, threads) = record_spans(op);
+    (r, TaskGraph::new(&spans, threads))
+}
@@ -0,0 +1,177 @@
+//! `tracing` subscriber keeping all spans in memory.
+use super::{FieldValue, RawSpan};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread::ThreadId;
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+#[derive(Default)]
+struct RecorderState {
+    spans: Vec<RawSpan>,
+    /// Number of handles on each span.
+    references: Vec<usize>,
+    /// Indices of threads outside of the recording pool.
+    foreign_threads: HashMap<ThreadId, usize>,
+}
+
+/// A `tracing` subscriber keeping all spans in memory.
+struct Recorder {
+    start: Instant,
+    threads: usize,
+    state: Mutex<RecorderState>,
+}
+
+thread_local! {
+    /// Spans currently entered on this thread.
+    static STACK: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+struct FieldsVisitor<'a>(&'a mut Vec<(&'static str, FieldValue)>);
+
+impl<'a> FieldsVisitor<'a> {
+    fn set(&mut self, field: &Field, value: FieldValue) {
+        match self.0.iter_mut().find(|(name, _)| *name == field.name()) {
+            Some((_, old_value)) => *old_value = value,
+            None => self.0.push((field.name(), value)),
+        }
+    }
+}
+
+impl<'a> Visit for FieldsVisitor<'a> {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.set(field, FieldValue::U64(value))
+    }
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field, FieldValue::I64(value))
+    }
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field, FieldValue::Bool(value))
+    }
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, FieldValue::Str(value.to_owned()))
+    }
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.set(field, FieldValue::Str(format!("{:?}", value)))
+    }
+}
+
+impl Recorder {
+    fn new(threads: usize) -> Self {
+        Recorder {
+            start: Instant::now(),
+            threads,
+            state: Mutex::new(RecorderState::default()),
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_nanos() as u64
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let parent = if attributes.is_contextual() {
+            STACK.with(|stack| stack.borrow().last().copied())
+        } else {
+            attributes.parent().map(|id| id.into_u64())
+        };
+        let mut fields = Vec::new();
+        attributes.record(&mut FieldsVisitor(&mut fields));
+        let start = self.now();
+        let mut state = self.state.lock().expect("poisoned recorder");
+        let thread = match rayon::current_thread_index() {
+            Some(index) => index,
+            None => {
+                let index = self.threads + state.foreign_threads.len();
+                *state
+                    .foreign_threads
+                    .entry(std::thread::current().id())
+                    .or_insert(index)
+            }
+        };
+        state.spans.push(RawSpan {
+            name: attributes.metadata().name(),
+            parent,
+            thread,
+            start,
+            end: None,
+            fields,
+        });
+        state.references.push(1);
+        Id::from_u64(state.spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut state = self.state.lock().expect("poisoned recorder");
+        let span = &mut state.spans[span.into_u64() as usize - 1];
+        values.record(&mut FieldsVisitor(&mut span.fields));
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        STACK.with(|stack| stack.borrow_mut().push(span.into_u64()))
+    }
+
+    fn exit(&self, span: &Id) {
+        STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            if let Some(position) = stack.iter().rposition(|&id| id == span.into_u64()) {
+                stack.remove(position);
+            }
+        })
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        let mut state = self.state.lock().expect("poisoned recorder");
+        state.references[span.into_u64() as usize - 1] += 1;
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let end = self.now();
+        let mut state = self.state.lock().expect("poisoned recorder");
+        let index = span.into_u64() as usize - 1;
+        state.references[index] -= 1;
+        if state.references[index] == 0 {
+            state.spans[index].end = Some(end);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Run `op` inside a dedicated thread pool where all spans are recorded.
+/// Return `op`'s result, all recorded spans and the number of threads in the pool.
+pub(super) fn record_spans<R, F>(op: F) -> (R, Vec<RawSpan>, usize)
+where
+    F: FnOnce() -> R + Send,
+    R: Send,
+{
+    let threads = rayon::current_num_threads();
+    let recorder = Arc::new(Recorder::new(threads));
+    let dispatch = tracing::Dispatch::from(recorder.clone());
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .start_handler(move |_| {
+            // the pool's threads record for their whole life
+            std::mem::forget(tracing::dispatcher::set_default(&dispatch))
+        })
+        .build()
+        .expect("failed building recording thread pool");
+    let r = pool.install(op);
+    let spans = std::mem::take(&mut recorder.state.lock().expect("poisoned recorder").spans);
+    (r, spans, threads)
+}
@@ -0,0 +1,295 @@
+//! Replay a recorded computation on virtual threads.
+use super::{NodeKind, TaskGraph};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+/// A sequential piece of work in the simulated dag.
+struct Strand {
+    node: Option<usize>,
+    duration: Duration,
+    successors: Vec<usize>,
+    predecessors: usize,
+}
+
+/// Dag of strands obtained from a `TaskGraph`.
+#[derive(Default)]
+struct Dag {
+    strands: Vec<Strand>,
+}
+
+impl Dag {
+    fn add_strand(&mut self, node: Option<usize>, duration: Duration) -> usize {
+        self.strands.push(Strand {
+            node,
+            duration,
+            successors: Vec::new(),
+            predecessors: 0,
+        });
+        self.strands.len() - 1
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize) {
+        self.strands[from].successors.push(to);
+        self.strands[to].predecessors += 1;
+    }
+
+    /// Add the strands of all tasks, returning the entry and exit strands of each task.
+    ///
+    /// Tasks are stored before their sub-tasks so a reverse pass
+    /// over all nodes sees sub-tasks first.
+    fn add_tasks(&mut self, graph: &TaskGraph) -> Vec<(usize, usize)> {
+        let mut ends = vec![(0, 0); graph.nodes().len()];
+        for (node, task) in graph.nodes().iter().enumerate().rev() {
+            ends[node] = if task.kind == NodeKind::Parallel {
+                let fork = self.add_strand(None, Duration::default());
+                let join = self.add_strand(None, Duration::default());
+                for &child in &task.children {
+                    let (entry, exit) = ends[child];
+                    self.add_edge(fork, entry);
+                    self.add_edge(exit, join);
+                }
+                (fork, join)
+            } else {
+                let entry = self.add_strand(Some(node), graph.exclusive_time(node));
+                let mut exit = entry;
+                for &child in &task.children {
+                    let (child_entry, child_exit) = ends[child];
+                    self.add_edge(exit, child_entry);
+                    exit = child_exit;
+                }
+                (entry, exit)
+            };
+        }
+        ends
+    }
+}
+
+/// A task executed during a simulation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledTask {
+    /// Index of the task in the simulated [`TaskGraph`].
+    ///
+    /// [`TaskGraph`]: struct.TaskGraph.html
+    pub node: usize,
+    /// Virtual thread executing the task.
+    pub thread: usize,
+    /// Simulated start time.
+    pub start: Duration,
+    /// Simulated end time.
+    pub end: Duration,
+}
+
+/// Result of the [`simulate()`] function.
+///
+/// [`simulate()`]: fn.simulate.html
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    threads: usize,
+    duration: Duration,
+    tasks: Vec<ScheduledTask>,
+    labels: Vec<(NodeKind, Option<String>)>,
+}
+
+impl Schedule {
+    /// Number of virtual threads used.
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Predicted completion time.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// All executed tasks, by increasing start times.
+    /// Only the time spent in a task but not in its sub-tasks appears.
+    pub fn tasks(&self) -> &[ScheduledTask] {
+        &self.tasks
+    }
+
+    /// Save the simulated schedule as an svg file, one line per thread.
+    pub fn svg<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        self.write_svg(std::io::BufWriter::new(file))
+    }
+
+    fn write_svg<W: Write>(&self, mut output: W) -> std::io::Result<()> {
+        const WIDTH: f64 = 1920.0;
+        const THREAD_HEIGHT: f64 = 40.0;
+        let scale = if self.duration.as_nanos() == 0 {
+            0.0
+        } else {
+            WIDTH / self.duration.as_nanos() as f64
+        };
+        writeln!(
+            output,
+            "<svg version=\"1.1\" xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\">",
+            WIDTH,
+            THREAD_HEIGHT * self.threads as f64
+        )?;
+        writeln!(
+            output,
+            "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>"
+        )?;
+        for task in &self.tasks {
+            let (kind, label) = &self.labels[task.node];
+            let color = match kind {
+                NodeKind::Fold => "green",
+                NodeKind::Reduce => "blue",
+                _ => "red",
+            };
+            writeln!(
+                output,
+                "<rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"{}\" stroke=\"black\" stroke-width=\"0.5\"><title>{:?} {} ({:?})</title></rect>",
+                task.start.as_nanos() as f64 * scale,
+                task.thread as f64 * THREAD_HEIGHT,
+                (task.end - task.start).as_nanos() as f64 * scale,
+                THREAD_HEIGHT * 0.9,
+                color,
+                kind,
+                label.as_deref().unwrap_or("").replace('&', "&amp;").replace('<', "&lt;"),
+                task.end - task.start
+            )?;
+        }
+        writeln!(output, "</svg>")
+    }
+}
+
+/// Replay the recorded computation on `threads` virtual threads,
+/// using a greedy work-stealing scheduler.
+///
+/// Sequential tasks keep their recorded durations.
+/// When a task is divided, the executing thread continues with the first sub-task
+/// and the others become available for stealing.
+/// Idle threads first take their most recently available task, and otherwise
+/// steal the oldest available task of another thread.
+/// Steals and synchronizations are considered free.
+///
+/// # Example
+///
+/// ```
+/// use rayon::prelude::*;
+/// use diam::prelude::*;
+/// let (_, graph) = diam::record(|| (0..100_000u64).into_par_iter().log("sum").sum::<u64>());
+/// let sequential = diam::simulate(&graph, 1);
+/// let parallel = diam::simulate(&graph, 64);
+/// assert!(parallel.duration() <= sequential.duration());
+/// assert_eq!(sequential.duration(), graph.stats().work);
+///
+/// // logged blocks are nested in each other, giving deep task trees
+/// let (_, graph) = diam::record(|| {
+///     (0..100_000u32)
+///         .into_par_iter()
+///         .log("blocks")
+///         .by_uniform_blocks(1)
+///         .count()
+/// });
+/// assert_eq!(diam::simulate(&graph, 1).duration(), graph.stats().work);
+/// ```
+pub fn simulate(graph: &TaskGraph, threads: usize) -> Schedule {
+    assert!(threads > 0, "simulating on zero threads");
+    let mut dag = Dag::default();
+    let ends = dag.add_tasks(graph);
+    let mut entry = None;
+    let mut exit: Option<usize> = None;
+    for &root in graph.roots() {
+        let (root_entry, root_exit) = ends[root];
+        match exit {
+            Some(previous_exit) => dag.add_edge(previous_exit, root_entry),
+            None => entry = Some(root_entry),
+        }
+        exit = Some(root_exit);
+    }
+    let mut simulator = Simulator {
+        dag,
+        deques: vec![VecDeque::new(); threads],
+        running: vec![None; threads],
+        events: BinaryHeap::new(),
+        tasks: Vec::new(),
+        now: Duration::default(),
+    };
+    if let Some(entry) = entry {
+        simulator.deques[0].push_back(entry);
+    }
+    simulator.run();
+    Schedule {
+        threads,
+        duration: simulator.now,
+        tasks: simulator.tasks,
+        labels: graph
+            .nodes()
+            .iter()
+            .map(|n| (n.kind, n.label.clone()))
+            .collect(),
+    }
+}
+
+/// Discrete events simulation of a work-stealing scheduler.
+struct Simulator {
+    dag: Dag,
+    deques: Vec<VecDeque<usize>>,
+    running: Vec<Option<usize>>,
+    /// End times of running strands, with their threads.
+    events: BinaryHeap<Reverse<(Duration, usize)>>,
+    tasks: Vec<ScheduledTask>,
+    now: Duration,
+}
+
+impl Simulator {
+    fn run(&mut self) {
+        let threads = self.deques.len();
+        loop {
+            // all idle threads look for work
+            for thread in 0..threads {
+                if self.running[thread].is_none() {
+                    let deques = &mut self.deques;
+                    let strand = deques[thread].pop_back().or_else(|| {
+                        (1..threads)
+                            .map(|offset| (thread + offset) % threads)
+                            .find_map(|victim| deques[victim].pop_front())
+                    });
+                    if let Some(strand) = strand {
+                        self.start(strand, thread);
+                    }
+                }
+            }
+            let Reverse((end, thread)) = match self.events.pop() {
+                Some(event) => event,
+                None => break,
+            };
+            self.now = end;
+            let strand = self.running[thread].take().expect("no running strand");
+            let mut ready = Vec::new();
+            for successor in std::mem::take(&mut self.dag.strands[strand].successors) {
+                self.dag.strands[successor].predecessors -= 1;
+                if self.dag.strands[successor].predecessors == 0 {
+                    ready.push(successor);
+                }
+            }
+            // continue with the first ready strand, others can be stolen
+            if let Some((&first, others)) = ready.split_first() {
+                self.deques[thread].extend(others.iter().rev());
+                self.start(first, thread);
+            }
+        }
+    }
+
+    fn start(&mut self, strand: usize, thread: usize) {
+        let end = self.now + self.dag.strands[strand].duration;
+        self.running[thread] = Some(strand);
+        self.events.push(Reverse((end, thread)));
+        if let Some(node) = self.dag.strands[strand].node {
+            if end > self.now {
+                self.tasks.push(ScheduledTask {
+                    node,
+                    thread,
+                    start: self.now,
+                    end,
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Work/span statistics of a recorded computation.
+#[cfg(feature = "logging")]
+use super::record_spans;
+use super::{NodeKind, TaskGraph};
+use std::fmt;
+use std::time::Duration;
+
+/// Classic fork-join metrics of a recorded computation,
+/// obtained with [`TaskGraph::stats()`] or printed by the [`stats()`] function.
+///
+/// [`TaskGraph::stats()`]: struct.TaskGraph.html#method.stats
+/// [`stats()`]: fn.stats.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// Total time spent executing tasks, over all threads.
+    pub work: Duration,
+    /// Length of the critical path.
+    pub span: Duration,
+    /// Wall-clock duration of the computation.
+    pub duration: Duration,
+    /// Average parallelism (work divided by span).
+    pub parallelism: f64,
+    /// For each thread, the time spent not executing any task.
+    pub idle: Vec<Duration>,
+    /// Number of root tasks and sub-tasks of divided tasks,
+    /// whether they were divided again or not.
+    pub tasks: usize,
+    /// Number of times a task was divided in two.
+    pub splits: usize,
+}
+
+impl TaskGraph {
+    /// Time spent in the given task but not in its sub-tasks.
+    /// Divided tasks only wait for their sub-tasks so they have none.
+    pub(super) fn exclusive_time(&self, node: usize) -> Duration {
+        let node = &self.nodes()[node];
+        if node.kind == NodeKind::Parallel {
+            Duration::default()
+        } else {
+            node.children
+                .iter()
+                .map(|&c| self.nodes()[c].duration())
+                .fold(node.duration(), |remaining, d| remaining.saturating_sub(d))
+        }
+    }
+
+    /// Length of the critical path starting at each task.
+    ///
+    /// Tasks are stored before their sub-tasks so a reverse pass
+    /// over all nodes sees sub-tasks first.
+    fn critical_paths(&self) -> Vec<Duration> {
+        let mut paths = vec![Duration::default(); self.nodes().len()];
+        for (index, node) in self.nodes().iter().enumerate().rev() {
+            let children = node.children.iter().map(|&c| paths[c]);
+            paths[index] = if node.kind == NodeKind::Parallel {
+                children.max().unwrap_or_default()
+            } else {
+                children.sum::<Duration>() + self.exclusive_time(index)
+            };
+        }
+        paths
+    }
+
+    /// Compute work, span, parallelism and idle times of the recorded computation.
+    ///
+    /// Root tasks are considered to be executed one after the other.
+    ///
+    /// # Example
+    ///
+    /// Logged blocks are nested in each other, giving deep task trees.
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use diam::prelude::*;
+    /// let (count, graph) = diam::record(|| {
+    ///     (0..100_000u32)
+    ///         .into_par_iter()
+    ///         .log("blocks")
+    ///         .by_uniform_blocks(1)
+    ///         .count()
+    /// });
+    /// assert_eq!(count, 100_000);
+    /// let stats = graph.stats();
+    /// assert!(stats.span <= stats.work);
+    /// ```
+    pub fn stats(&self) -> Stats {
+        let mut busy = vec![Duration::default(); self.threads()];
+        for (index, node) in self.nodes().iter().enumerate() {
+            busy[node.thread] += self.exclusive_time(index);
+        }
+        let work: Duration = busy.iter().sum();
+        let paths = self.critical_paths();
+        let span: Duration = self.roots().iter().map(|&r| paths[r]).sum();
+        let duration = self.duration();
+        let parallelism = if span.as_nanos() == 0 {
+            0.0
+        } else {
+            work.as_secs_f64() / span.as_secs_f64()
+        };
+        let tasks = self
+            .nodes()
+            .iter()
+            .filter(|n| n.kind == NodeKind::Left || n.kind == NodeKind::Right)
+            .count();
+        let splits = self
+            .nodes()
+            .iter()
+            .filter(|n| n.kind == NodeKind::Parallel)
+            .count();
+        Stats {
+            work,
+            span,
+            duration,
+            parallelism,
+            idle: busy.iter().map(|&b| duration.saturating_sub(b)).collect(),
+            tasks,
+            splits,
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "duration:    {:?}", self.duration)?;
+        writeln!(f, "work:        {:?}", self.work)?;
+        writeln!(f, "span:        {:?}", self.span)?;
+        writeln!(f, "parallelism: {:.2}", self.parallelism)?;
+        writeln!(f, "tasks:       {}", self.tasks)?;
+        writeln!(f, "splits:      {}", self.splits)?;
+        for (thread, idle) in self.idle.iter().enumerate() {
+            writeln!(f, "idle on thread {}: {:?}", thread, idle)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `op`, recording all logged tasks, and print work/span statistics
+/// on the standard output.
+///
+/// `op` is run inside a dedicated thread pool with as many threads as the current one.
+/// See [`record()`] and [`TaskGraph::stats()`] to access the numbers directly.
+///
+/// [`record()`]: fn.record.html
+/// [`TaskGraph::stats()`]: struct.TaskGraph.html#method.stats
+///
+/// # Example
+///
+/// ```
+/// use rayon::prelude::*;
+/// use diam::prelude::*;
+/// let s = diam::stats(|| (0..10_000u32).into_par_iter().log("sum").sum::<u32>());
+/// assert_eq!(s, 49_995_000);
+///
+/// let (_, graph) = diam::record(|| (0..10_000u32).into_par_iter().log("sum").sum::<u32>());
+/// let stats = graph.stats();
+/// assert!(stats.span <= stats.work);
+/// assert_eq!(stats.tasks, 2 * stats.splits + 1);
+/// ```
+#[cfg(feature = "logging")]
+pub fn stats<R, F>(op: F) -> R
+where
+    F: FnOnce() -> R + Send,
+    R: Send,
+{
+    let (r, spans, threads) = record_spans(op);
+    print!("{}", TaskGraph::new(&spans, threads).stats());
+    r
+}
+
+/// Run `op`. Since the `logging` feature is disabled no statistics are printed.
+#[cfg(not(feature = "logging"))]
+pub fn stats<R, F>(op: F) -> R
+where
+    F: FnOnce() -> R + Send,
+    R: Send,
+{
+    op()
+}